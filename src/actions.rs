@@ -0,0 +1,233 @@
+use std::fmt;
+
+use crate::{
+    audio::{AudioBackend, Target},
+    config,
+    context::Context,
+    error::Result,
+};
+use default_device::DefaultDevice;
+use endpoint_volume::EndpointVolume;
+use enum_dispatch::enum_dispatch;
+use foreground_volume::ForegroundVolume;
+use log::warn;
+use log_value::Log;
+use midly::num::{u4, u7};
+use run::Run;
+use selection::Selection;
+use serde::Deserialize;
+use session_volume::SessionVolume;
+
+pub(crate) mod default_device;
+pub(crate) mod endpoint_volume;
+pub(crate) mod foreground_volume;
+pub(crate) mod log_value;
+pub(crate) mod run;
+pub(crate) mod selection;
+pub(crate) mod session_volume;
+
+/// Where the message that fired a trigger came from, for triggers that
+/// cover several channels or notes/controllers, and what it carried. Empty
+/// for messages that don't have these.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub(crate) struct TriggeredBy {
+    pub(crate) channel: Option<u4>,
+    /// The note or controller
+    pub(crate) number: Option<u7>,
+    /// For triggers that capture a value from the message
+    pub(crate) captured: Option<Captured>,
+}
+
+/// A value captured from the bytes of a message, both as it came and scaled
+/// to the range of the bytes it came from.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct Captured {
+    pub(crate) raw: u32,
+    /// From 0.0 to 1.0
+    pub(crate) value: f32,
+}
+
+/// What a control hands to the action it is bound to.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum ControlValue {
+    /// A button was pressed, or some other one-off message came in
+    Trigger(TriggeredBy),
+    /// From 0.0 to 1.0
+    Absolute(f32),
+    /// Steps up (positive) or down (negative)
+    Relative(i32),
+    /// A latching or radio button, with its new state
+    Switch(bool),
+    /// Taken from the message, used like an absolute value
+    Captured(Captured),
+}
+
+impl fmt::Display for ControlValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Trigger(by) => {
+                write!(f, "trigger")?;
+                if let Some(channel) = by.channel {
+                    write!(f, " on channel {channel}")?;
+                }
+                if let Some(number) = by.number {
+                    write!(f, " from {number}")?;
+                }
+                Ok(())
+            }
+            Self::Absolute(value) => write!(f, "{value:.3}"),
+            Self::Relative(steps) => write!(f, "{steps:+}"),
+            Self::Switch(on) => write!(f, "{}", if *on { "on" } else { "off" }),
+            Self::Captured(captured) => write!(f, "{}", captured.raw),
+        }
+    }
+}
+
+#[enum_dispatch]
+pub(crate) trait Action {
+    fn try_perform(&self, value: ControlValue, context: &mut Context) -> Result<()>;
+
+    /// Whether whatever the action controls is currently on, e.g. muted, for
+    /// lighting up LEDs. `None` if there is no such thing.
+    fn state(&self, _context: &mut Context) -> Option<bool> {
+        None
+    }
+
+    /// Where whatever the action controls currently is, from 0.0 to 1.0, e.g.
+    /// a volume, for indicators to show. `None` if there is no such thing.
+    fn level(&self, _context: &mut Context) -> Option<f32> {
+        None
+    }
+}
+
+/// The actions a control can be bound to, picked by `name` in the config with
+/// the parameters next to it.
+#[derive(Debug, Deserialize)]
+#[enum_dispatch(Action)]
+#[serde(remote = "Self", rename_all = "snake_case")]
+pub(crate) enum ActionType {
+    Log(Log),
+    Run(Run),
+    SessionVolume(SessionVolume),
+    ForegroundVolume(ForegroundVolume),
+    EndpointVolume(EndpointVolume),
+    DefaultDevice(DefaultDevice),
+    Selection(Selection),
+}
+
+config::tagged_enum!(ActionType, "name");
+
+impl Default for ActionType {
+    fn default() -> Self {
+        Self::Log(Log::default())
+    }
+}
+
+impl ActionType {
+    /// Controls have nobody to report to, so failures are only logged.
+    pub(crate) fn perform(&self, value: ControlValue, context: &mut Context) {
+        if let Err(error) = self.try_perform(value, context) {
+            warn!("Failed to perform {self:?} with {value}: {error}");
+        }
+    }
+}
+
+fn default_step() -> f32 {
+    0.02
+}
+
+/// What the volume actions have in common: absolute values set the volume,
+/// relative values nudge it by `step` per step, triggers toggle mute and
+/// switches mute while on.
+fn adjust_volume(
+    audio: &mut dyn AudioBackend,
+    targets: &[Target],
+    value: ControlValue,
+    step: f32,
+) -> Result<()> {
+    match value {
+        ControlValue::Trigger(_) => {
+            // Mute all of them if any is unmuted, so they end up in sync
+            let mut muted = true;
+            for target in targets {
+                muted &= audio.muted(target)?;
+            }
+            for target in targets {
+                audio.set_muted(target, !muted)?;
+            }
+        }
+        ControlValue::Switch(on) => {
+            for target in targets {
+                audio.set_muted(target, on)?;
+            }
+        }
+        ControlValue::Absolute(volume) | ControlValue::Captured(Captured { value: volume, .. }) => {
+            for target in targets {
+                audio.set_volume(target, volume)?;
+            }
+        }
+        ControlValue::Relative(steps) => {
+            #[allow(clippy::cast_precision_loss)]
+            let delta = steps as f32 * step;
+            for target in targets {
+                let volume = (audio.volume(target)? + delta).clamp(0.0, 1.0);
+                audio.set_volume(target, volume)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// On while there are targets and all of them are muted
+fn all_muted(audio: &dyn AudioBackend, targets: &[Target]) -> Option<bool> {
+    let mut muted = !targets.is_empty();
+    for target in targets {
+        muted &= audio.muted(target).ok()?;
+    }
+    Some(muted)
+}
+
+/// The volume of the loudest of `targets`, none if there aren't any
+fn loudest(audio: &dyn AudioBackend, targets: &[Target]) -> Option<f32> {
+    let mut level = None;
+    for target in targets {
+        let volume = audio.volume(target).ok()?;
+        level = Some(level.map_or(volume, |level: f32| level.max(volume)));
+    }
+    level
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn display() {
+        assert_eq!(
+            ControlValue::Trigger(TriggeredBy::default()).to_string(),
+            "trigger"
+        );
+        assert_eq!(ControlValue::Absolute(0.25).to_string(), "0.250");
+        assert_eq!(ControlValue::Relative(2).to_string(), "+2");
+        assert_eq!(ControlValue::Relative(-2).to_string(), "-2");
+        assert_eq!(ControlValue::Switch(true).to_string(), "on");
+        assert_eq!(ControlValue::Switch(false).to_string(), "off");
+        let captured = Captured {
+            raw: 8192,
+            value: 8192.0 / 16383.0,
+        };
+        assert_eq!(ControlValue::Captured(captured).to_string(), "8192");
+    }
+
+    #[test]
+    fn by_name() {
+        let action: ActionType = toml::from_str("message = \"Fader\"\nname = \"log\"").unwrap();
+        assert!(
+            matches!(action, ActionType::Log(Log { message: Some(message) }) if message == "Fader")
+        );
+        let error = toml::from_str::<ActionType>("name = \"run\"\nargs = []").unwrap_err();
+        assert_eq!(error.message(), "missing field `program`");
+        let error = toml::from_str::<ActionType>("name = \"launch\"").unwrap_err();
+        assert_eq!(error.span(), Some(7..15));
+    }
+}
@@ -0,0 +1,156 @@
+use serde::Deserialize;
+use wildmatch::WildMatch;
+
+use crate::{
+    audio::{Device, Flow, Role},
+    config,
+    context::Context,
+    error::Result,
+};
+
+use super::{Action, Captured, ControlValue};
+
+/// Switches the default device between `devices`. Triggers go to the next one,
+/// relative values move through the list and absolute values pick one
+/// directly. Switches pick the first one while on and the second while off.
+/// A single device makes a button that jumps straight to it.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct DefaultDevice {
+    /// Device names, e.g. "Headset*" and "Speakers*", case insensitive. Those
+    /// that aren't connected are skipped.
+    #[serde(deserialize_with = "config::name_patterns")]
+    pub(crate) devices: Vec<WildMatch>,
+    #[serde(default)]
+    pub(crate) flow: Flow,
+    /// The roles to change the default for, the first one decides where in
+    /// the list we are. Defaults to what the Sound control panel sets.
+    #[serde(default = "DefaultDevice::default_roles")]
+    pub(crate) roles: Vec<Role>,
+}
+
+impl DefaultDevice {
+    fn default_roles() -> Vec<Role> {
+        vec![Role::Console, Role::Multimedia]
+    }
+
+    /// The ids of the first connected device for each of `devices`
+    fn candidates(&self, context: &Context) -> Vec<String> {
+        let connected = context.audio.backend().devices();
+        self.devices
+            .iter()
+            .filter_map(|pattern| {
+                connected
+                    .iter()
+                    .find(|device| {
+                        device.active && device.flow == self.flow && pattern.matches(&device.name)
+                    })
+                    .map(|device| device.id.clone())
+            })
+            .collect()
+    }
+
+    fn current_default(&self, context: &Context) -> Option<Device> {
+        context
+            .audio
+            .backend()
+            .default_device(self.flow, *self.roles.first()?)
+    }
+
+    /// Where in `candidates` the current default device is
+    fn current(&self, candidates: &[String], context: &Context) -> Option<usize> {
+        let id = self.current_default(context)?.id;
+        candidates.iter().position(|candidate| *candidate == id)
+    }
+}
+
+/// Which of `count` candidates `value` goes to from `current`. Without a
+/// current one, moving through the list starts at the first.
+fn pick(value: ControlValue, current: Option<usize>, count: usize) -> usize {
+    #[allow(
+        clippy::cast_possible_truncation,
+        clippy::cast_possible_wrap,
+        clippy::cast_precision_loss,
+        clippy::cast_sign_loss
+    )]
+    match value {
+        ControlValue::Trigger(_) => current.map_or(0, |current| (current + 1) % count),
+        ControlValue::Relative(steps) => current.map_or(0, |current| {
+            (current as i64 + i64::from(steps)).rem_euclid(count as i64) as usize
+        }),
+        ControlValue::Absolute(value) | ControlValue::Captured(Captured { value, .. }) => {
+            (value.clamp(0.0, 1.0) * (count - 1) as f32).round() as usize
+        }
+        ControlValue::Switch(on) => usize::from(!on) % count,
+    }
+}
+
+impl Action for DefaultDevice {
+    fn try_perform(&self, value: ControlValue, context: &mut Context) -> Result<()> {
+        let candidates = self.candidates(context);
+        if candidates.is_empty() {
+            return Ok(());
+        }
+        let current = self.current(&candidates, context);
+        let index = pick(value, current, candidates.len());
+        if current == Some(index) {
+            return Ok(());
+        }
+        for role in &self.roles {
+            context
+                .audio
+                .backend_mut()
+                .set_default_device(&candidates[index], *role)?;
+        }
+        Ok(())
+    }
+
+    /// On while the default device is the first of `devices`
+    fn state(&self, context: &mut Context) -> Option<bool> {
+        let first = self.devices.first()?;
+        Some(first.matches(&self.current_default(context)?.name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::actions::TriggeredBy;
+
+    const TRIGGER: ControlValue = ControlValue::Trigger(TriggeredBy {
+        channel: None,
+        number: None,
+        captured: None,
+    });
+
+    #[test]
+    fn triggers_go_round() {
+        assert_eq!(pick(TRIGGER, None, 3), 0);
+        assert_eq!(pick(TRIGGER, Some(0), 3), 1);
+        assert_eq!(pick(TRIGGER, Some(2), 3), 0);
+        assert_eq!(pick(TRIGGER, Some(0), 1), 0);
+    }
+
+    #[test]
+    fn relative_values_wrap() {
+        assert_eq!(pick(ControlValue::Relative(1), None, 3), 0);
+        assert_eq!(pick(ControlValue::Relative(-1), Some(0), 3), 2);
+        assert_eq!(pick(ControlValue::Relative(4), Some(1), 3), 2);
+    }
+
+    #[test]
+    fn absolute_values_spread_over_the_list() {
+        assert_eq!(pick(ControlValue::Absolute(0.0), Some(2), 3), 0);
+        assert_eq!(pick(ControlValue::Absolute(0.5), None, 3), 1);
+        assert_eq!(pick(ControlValue::Absolute(1.0), None, 3), 2);
+        assert_eq!(pick(ControlValue::Absolute(2.0), None, 3), 2);
+    }
+
+    #[test]
+    fn roles_default_to_the_control_panel() {
+        let action: DefaultDevice =
+            toml::from_str("devices = [\"Headset*\", \"Speakers*\"]").unwrap();
+        assert_eq!(action.roles, [Role::Console, Role::Multimedia]);
+        assert!(action.devices[1].matches("speakers (Realtek Audio)"));
+    }
+}
@@ -0,0 +1,76 @@
+use serde::Deserialize;
+use wildmatch::WildMatch;
+
+use crate::{
+    audio::{Flow, Role},
+    config,
+    context::Context,
+    error::Result,
+};
+
+use super::{adjust_volume, all_muted, default_step, loudest, Action, ControlValue};
+
+/// The master volume of audio devices. By default the one that is currently
+/// the default for `flow` and `role`, so that "the speakers" or "the
+/// microphone" stay under control whichever physical device that is.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct EndpointVolume {
+    /// Picks devices by name instead, e.g. "Speakers*", case insensitive
+    #[serde(default, deserialize_with = "config::optional_name_pattern")]
+    pub(crate) device: Option<WildMatch>,
+    #[serde(default)]
+    pub(crate) flow: Flow,
+    /// Only used for the default device
+    #[serde(default)]
+    pub(crate) role: Role,
+    /// How much of the full range a single relative step moves the volume
+    #[serde(default = "default_step")]
+    pub(crate) step: f32,
+}
+
+impl Action for EndpointVolume {
+    fn try_perform(&self, value: ControlValue, context: &mut Context) -> Result<()> {
+        let endpoints = context
+            .audio
+            .endpoints(self.flow, self.role, self.device.as_ref());
+        adjust_volume(context.audio.backend_mut(), &endpoints, value, self.step)
+    }
+
+    fn state(&self, context: &mut Context) -> Option<bool> {
+        let endpoints = context
+            .audio
+            .endpoints(self.flow, self.role, self.device.as_ref());
+        all_muted(context.audio.backend(), &endpoints)
+    }
+
+    fn level(&self, context: &mut Context) -> Option<f32> {
+        let endpoints = context
+            .audio
+            .endpoints(self.flow, self.role, self.device.as_ref());
+        loudest(context.audio.backend(), &endpoints)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn defaults_to_the_default_device() {
+        let action: EndpointVolume = toml::from_str("").unwrap();
+        assert!(action.device.is_none());
+        assert_eq!(action.flow, Flow::Render);
+        assert_eq!(action.role, Role::Console);
+    }
+
+    #[test]
+    fn device_by_name() {
+        let action: EndpointVolume =
+            toml::from_str("device = \"headset*\"\nflow = \"capture\"\nrole = \"communications\"")
+                .unwrap();
+        assert!(action.device.unwrap().matches("Headset Microphone"));
+        assert_eq!(action.flow, Flow::Capture);
+        assert_eq!(action.role, Role::Communications);
+    }
+}
@@ -0,0 +1,37 @@
+use serde::Deserialize;
+
+use crate::{context::Context, error::Result};
+
+use super::{adjust_volume, all_muted, default_step, loudest, Action, ControlValue};
+
+/// Like `SessionVolume`, but for whichever application owns the foreground
+/// window, including its child processes.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct ForegroundVolume {
+    /// Keep targeting the last foreground application that had audio while
+    /// the foreground window has none, e.g. after switching from a music
+    /// player to a text editor
+    #[serde(default)]
+    pub(crate) keep_last_audible: bool,
+    /// How much of the full range a single relative step moves the volume
+    #[serde(default = "default_step")]
+    pub(crate) step: f32,
+}
+
+impl Action for ForegroundVolume {
+    fn try_perform(&self, value: ControlValue, context: &mut Context) -> Result<()> {
+        let sessions = context.audio.foreground_sessions(self.keep_last_audible);
+        adjust_volume(context.audio.backend_mut(), &sessions, value, self.step)
+    }
+
+    fn state(&self, context: &mut Context) -> Option<bool> {
+        let sessions = context.audio.foreground_sessions(self.keep_last_audible);
+        all_muted(context.audio.backend(), &sessions)
+    }
+
+    fn level(&self, context: &mut Context) -> Option<f32> {
+        let sessions = context.audio.foreground_sessions(self.keep_last_audible);
+        loudest(context.audio.backend(), &sessions)
+    }
+}
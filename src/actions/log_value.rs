@@ -0,0 +1,27 @@
+use log::info;
+use serde::Deserialize;
+
+use crate::{context::Context, error::Result};
+
+use super::{Action, ControlValue};
+
+/// Logs the value, handy for finding out what a control sends. This is what
+/// controls without an action do.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct Log {
+    /// Logged in front of the value
+    #[serde(default)]
+    pub(crate) message: Option<String>,
+}
+
+impl Action for Log {
+    fn try_perform(&self, value: ControlValue, _context: &mut Context) -> Result<()> {
+        if let Some(message) = &self.message {
+            info!("{message}: {value}");
+        } else {
+            info!("{value}");
+        }
+        Ok(())
+    }
+}
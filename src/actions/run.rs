@@ -0,0 +1,108 @@
+use std::{process::Command, thread};
+
+use log::{debug, warn};
+use serde::Deserialize;
+
+use crate::{context::Context, error::Result};
+
+use super::{Action, ControlValue};
+
+/// Starts a program, without waiting for it to finish. Any `{value}` in the
+/// arguments is replaced by the value of the control, and for triggers any
+/// `{channel}` and `{number}` by where the message came from.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct Run {
+    pub(crate) program: String,
+    #[serde(default)]
+    pub(crate) args: Vec<String>,
+}
+
+impl Run {
+    fn args(&self, value: ControlValue) -> Vec<String> {
+        let (channel, number) = match value {
+            ControlValue::Trigger(by) => (
+                by.channel.map(|channel| channel.to_string()),
+                by.number.map(|number| number.to_string()),
+            ),
+            _ => (None, None),
+        };
+        let value = value.to_string();
+        self.args
+            .iter()
+            .map(|arg| {
+                arg.replace("{value}", &value)
+                    .replace("{channel}", channel.as_deref().unwrap_or_default())
+                    .replace("{number}", number.as_deref().unwrap_or_default())
+            })
+            .collect()
+    }
+}
+
+impl Action for Run {
+    fn try_perform(&self, value: ControlValue, _context: &mut Context) -> Result<()> {
+        let mut child = Command::new(&self.program).args(self.args(value)).spawn()?;
+        debug!("Started {} with pid {}", self.program, child.id());
+        // Somebody has to wait for it, or it lingers as a zombie once it exits
+        let program = self.program.clone();
+        thread::spawn(move || match child.wait() {
+            Ok(status) if !status.success() => warn!("{program} exited with {status}"),
+            Ok(_) => (),
+            Err(error) => warn!("Failed to wait for {program}: {error}"),
+        });
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use midly::num::{u4, u7};
+
+    use super::*;
+    use crate::actions::{Captured, TriggeredBy};
+
+    #[test]
+    fn args() {
+        let run: Run = toml::from_str(
+            "program = \"nircmd\"\nargs = [\"setvolume\", \"{value}\", \"{value}{value}\"]",
+        )
+        .unwrap();
+        assert_eq!(
+            run.args(ControlValue::Absolute(0.5)),
+            ["setvolume", "0.500", "0.5000.500"]
+        );
+        assert_eq!(
+            run.args(ControlValue::Relative(-3)),
+            ["setvolume", "-3", "-3-3"]
+        );
+        assert_eq!(
+            run.args(ControlValue::Trigger(TriggeredBy::default())),
+            ["setvolume", "trigger", "triggertrigger"]
+        );
+    }
+
+    #[test]
+    fn args_for_triggers() {
+        let run: Run =
+            toml::from_str("program = \"obs\"\nargs = [\"scene{number}\", \"{channel}\"]").unwrap();
+        let by = TriggeredBy {
+            channel: Some(u4::new(9)),
+            number: Some(u7::new(36)),
+            captured: None,
+        };
+        assert_eq!(run.args(ControlValue::Trigger(by)), ["scene36", "9"]);
+        // Nothing to fill in for other kinds of values
+        assert_eq!(run.args(ControlValue::Absolute(1.0)), ["scene", ""]);
+    }
+
+    #[test]
+    fn args_for_captured_values() {
+        let run: Run = toml::from_str("program = \"obs\"\nargs = [\"{value}\"]").unwrap();
+        let captured = Captured {
+            raw: 0x7F,
+            value: 1.0,
+        };
+        // As they came, not scaled
+        assert_eq!(run.args(ControlValue::Captured(captured)), ["127"]);
+    }
+}
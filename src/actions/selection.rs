@@ -0,0 +1,37 @@
+use std::collections::HashMap;
+
+use serde::Deserialize;
+
+use crate::{context::Context, error::Result};
+
+use super::{Action, ActionType, ControlValue};
+
+/// Passes the value on to a different action depending on which member of a
+/// radio group is selected, e.g. to pick the application a knob controls with
+/// a row of buttons. Does nothing while none of `actions` is selected.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct Selection {
+    pub(crate) group: String,
+    /// By member name
+    pub(crate) actions: HashMap<String, ActionType>,
+}
+
+impl Selection {
+    fn selected(&self, context: &Context) -> Option<&ActionType> {
+        self.actions.get(context.selected(&self.group)?)
+    }
+}
+
+impl Action for Selection {
+    fn try_perform(&self, value: ControlValue, context: &mut Context) -> Result<()> {
+        match self.selected(context) {
+            Some(action) => action.try_perform(value, context),
+            None => Ok(()),
+        }
+    }
+
+    fn state(&self, context: &mut Context) -> Option<bool> {
+        self.selected(context)?.state(context)
+    }
+}
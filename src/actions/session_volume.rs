@@ -0,0 +1,51 @@
+use serde::Deserialize;
+use wildmatch::WildMatch;
+
+use crate::{config, context::Context, error::Result};
+
+use super::{adjust_volume, all_muted, default_step, loudest, Action, ControlValue};
+
+/// The volume of the audio sessions of applications, picked by process name.
+/// Absolute values set the volume, relative values nudge it and triggers
+/// toggle mute.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct SessionVolume {
+    /// e.g. "spotify.exe" or "Discord*", case insensitive
+    #[serde(deserialize_with = "config::name_pattern")]
+    pub(crate) process: WildMatch,
+    /// How much of the full range a single relative step moves the volume
+    #[serde(default = "default_step")]
+    pub(crate) step: f32,
+}
+
+impl Action for SessionVolume {
+    fn try_perform(&self, value: ControlValue, context: &mut Context) -> Result<()> {
+        let sessions = context.audio.sessions_matching(&self.process);
+        adjust_volume(context.audio.backend_mut(), &sessions, value, self.step)
+    }
+
+    fn state(&self, context: &mut Context) -> Option<bool> {
+        let sessions = context.audio.sessions_matching(&self.process);
+        all_muted(context.audio.backend(), &sessions)
+    }
+
+    fn level(&self, context: &mut Context) -> Option<f32> {
+        let sessions = context.audio.sessions_matching(&self.process);
+        loudest(context.audio.backend(), &sessions)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn process_pattern() {
+        let action: SessionVolume = toml::from_str("process = \"spotify*\"").unwrap();
+        assert!(action.process.matches("Spotify.exe"));
+        assert!(action.process.matches("spotify"));
+        assert!(!action.process.matches("Discord.exe"));
+        assert_eq!(action.step, 0.02);
+    }
+}
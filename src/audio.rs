@@ -0,0 +1,304 @@
+use std::{collections::HashSet, fmt};
+
+use serde::Deserialize;
+use sysinfo::{Pid, ProcessRefreshKind, System};
+use wildmatch::WildMatch;
+
+use crate::error::Result;
+
+#[cfg(feature = "mock")]
+pub mod mock;
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, Hash, PartialEq, strum::Display)]
+#[serde(rename_all = "snake_case")]
+pub enum Flow {
+    #[default]
+    Render,
+    Capture,
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, Hash, PartialEq, strum::Display)]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    #[default]
+    Console,
+    Multimedia,
+    Communications,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Device {
+    pub id: String,
+    pub name: String,
+    pub flow: Flow,
+    pub active: bool,
+}
+
+/// A stream of audio that an application plays, with its own volume.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Session {
+    pub id: String,
+    pub device_id: String,
+    pub name: String,
+    pub process_name: Option<String>,
+    pub pid: u32,
+}
+
+/// Anything with a volume and mute of its own.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub enum Target {
+    Device(String),
+    Session(String),
+}
+
+/// The changes that can make a difference to what actions do, as opposed to
+/// e.g. a new icon for a session.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Change {
+    DefaultDevice {
+        flow: Flow,
+        role: Role,
+        device: String,
+    },
+    DeviceAdded(String),
+    DeviceRemoved(String),
+    DeviceState {
+        device: String,
+        state: String,
+    },
+    SessionCreated {
+        device: String,
+        session: String,
+    },
+    SessionRemoved {
+        device: String,
+        session: String,
+    },
+    Mute {
+        device: String,
+        session: String,
+        mute: bool,
+    },
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::DefaultDevice { flow, role, device } => {
+                write!(
+                    f,
+                    "Default device changed: Flow={flow}, Role={role}, Device={device}"
+                )
+            }
+            Self::DeviceAdded(device) => write!(f, "Device added: {device}"),
+            Self::DeviceRemoved(device) => write!(f, "Device removed: {device}"),
+            Self::DeviceState { device, state } => {
+                write!(f, "Device state changed: Device={device}, State={state}")
+            }
+            Self::SessionCreated { device, session } => {
+                write!(f, "Session created: Device={device}, Session={session}")
+            }
+            Self::SessionRemoved { device, session } => {
+                write!(f, "Session removed: Device={device}, Session={session}")
+            }
+            Self::Mute {
+                device,
+                session,
+                mute,
+            } => write!(
+                f,
+                "Mute changed: Device={device}, Session={session}, Mute={mute}"
+            ),
+        }
+    }
+}
+
+/// What an operating system's audio stack has to offer for actions to work
+/// on. Backends are created with a callback that they call, from any thread,
+/// whenever something may have changed, after which `update` picks up the
+/// changes on the thread that owns the backend.
+pub trait AudioBackend {
+    /// Applies whatever changed since the last call.
+    fn update(&mut self) -> Result<Vec<Change>>;
+    /// Every device, including the ones that aren't active.
+    fn devices(&self) -> Vec<Device>;
+    /// The sessions on every active render device.
+    fn sessions(&self) -> Vec<Session>;
+    fn default_device(&self, flow: Flow, role: Role) -> Option<Device>;
+    /// Makes the device with `id` the default for `role`, and whichever
+    /// direction it goes in.
+    fn set_default_device(&mut self, id: &str, role: Role) -> Result<()>;
+    /// From 0.0 to 1.0
+    fn volume(&self, target: &Target) -> Result<f32>;
+    fn set_volume(&mut self, target: &Target, volume: f32) -> Result<()>;
+    fn muted(&self, target: &Target) -> Result<bool>;
+    fn set_muted(&mut self, target: &Target, muted: bool) -> Result<()>;
+}
+
+/// The backend for the operating system we're running on.
+pub fn system_backend<F>(on_change: F) -> Result<Box<dyn AudioBackend>>
+where
+    F: Fn() + Send + Sync + 'static,
+{
+    #[cfg(windows)]
+    let backend = crate::windows_audio::WindowsAudio::new(on_change)?;
+    #[cfg(target_os = "linux")]
+    let backend = crate::pulse_audio::PulseAudio::new(on_change)?;
+    Ok(Box::new(backend))
+}
+
+/// An `AudioBackend` together with the lookups that are the same for all of
+/// them.
+pub struct Audio {
+    backend: Box<dyn AudioBackend>,
+    /// Refreshed fully when the foreground window changes
+    system: System,
+    /// The process tree of the foreground window
+    foreground: HashSet<u32>,
+    /// The most recent foreground process tree that had any sessions
+    last_audible: HashSet<u32>,
+}
+
+impl Audio {
+    pub fn new(backend: Box<dyn AudioBackend>) -> Self {
+        Self {
+            backend,
+            system: System::new(),
+            foreground: HashSet::new(),
+            last_audible: HashSet::new(),
+        }
+    }
+
+    pub fn backend(&self) -> &dyn AudioBackend {
+        self.backend.as_ref()
+    }
+
+    pub fn backend_mut(&mut self) -> &mut dyn AudioBackend {
+        self.backend.as_mut()
+    }
+
+    /// See `AudioBackend::update`.
+    pub fn update(&mut self) -> Result<Vec<Change>> {
+        let changes = self.backend.update()?;
+        if changes
+            .iter()
+            .any(|change| matches!(change, Change::SessionCreated { .. }))
+            && self.has_foreground_sessions()
+        {
+            self.last_audible.clone_from(&self.foreground);
+        }
+        Ok(changes)
+    }
+
+    /// The sessions that belong to a process whose name matches `process`.
+    pub fn sessions_matching(&self, process: &WildMatch) -> Vec<Target> {
+        self.backend
+            .sessions()
+            .into_iter()
+            .filter(|session| {
+                session
+                    .process_name
+                    .as_ref()
+                    .is_some_and(|name| process.matches(name))
+            })
+            .map(|session| Target::Session(session.id))
+            .collect()
+    }
+
+    /// The sessions that belong to the foreground window's process or any of
+    /// its children. With `keep_last_audible`, a foreground window without
+    /// sessions leaves the last one that had them targeted instead.
+    pub fn foreground_sessions(&self, keep_last_audible: bool) -> Vec<Target> {
+        let pids = if keep_last_audible && !self.has_foreground_sessions() {
+            &self.last_audible
+        } else {
+            &self.foreground
+        };
+        self.backend
+            .sessions()
+            .into_iter()
+            .filter(|session| pids.contains(&session.pid))
+            .map(|session| Target::Session(session.id))
+            .collect()
+    }
+
+    /// To be called with the process id of the new foreground window.
+    pub fn foreground_changed(&mut self, pid: u32) {
+        self.system
+            .refresh_processes_specifics(ProcessRefreshKind::new());
+        self.foreground = pid_and_child_pids(Pid::from_u32(pid), &self.system);
+        if self.has_foreground_sessions() {
+            self.last_audible.clone_from(&self.foreground);
+        }
+    }
+
+    /// The active devices for `flow` whose name matches `name`, or the
+    /// default device for `flow` and `role` without a name.
+    pub fn endpoints(&self, flow: Flow, role: Role, name: Option<&WildMatch>) -> Vec<Target> {
+        let devices = match name {
+            Some(name) => self
+                .backend
+                .devices()
+                .into_iter()
+                .filter(|device| device.active && device.flow == flow && name.matches(&device.name))
+                .collect(),
+            None => self
+                .backend
+                .default_device(flow, role)
+                .into_iter()
+                .collect::<Vec<_>>(),
+        };
+        devices
+            .into_iter()
+            .map(|device| Target::Device(device.id))
+            .collect()
+    }
+
+    fn has_foreground_sessions(&self) -> bool {
+        self.backend
+            .sessions()
+            .iter()
+            .any(|session| self.foreground.contains(&session.pid))
+    }
+}
+
+fn pid_and_child_pids(parent_pid: Pid, system: &System) -> HashSet<u32> {
+    let mut children = vec![HashSet::from([parent_pid])];
+    loop {
+        let new_children = system
+            .processes()
+            .iter()
+            .filter_map(|(pid, proc)| {
+                if proc
+                    .parent()
+                    .is_some_and(|parent| children.last().unwrap().contains(&parent))
+                {
+                    Some(*pid)
+                } else {
+                    None
+                }
+            })
+            .collect::<HashSet<_>>();
+        if new_children.is_empty() {
+            break;
+        }
+        children.push(new_children);
+    }
+    children.into_iter().flatten().map(Pid::as_u32).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn child_pids() {
+        let mut system = System::new();
+        system.refresh_processes_specifics(ProcessRefreshKind::new());
+        let pid = Pid::from_u32(std::process::id());
+        let pids = pid_and_child_pids(pid, &system);
+        assert!(pids.contains(&pid.as_u32()));
+        let parent = system.process(pid).and_then(sysinfo::Process::parent);
+        assert!(!pids.contains(&parent.unwrap().as_u32()));
+    }
+}
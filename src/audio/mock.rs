@@ -0,0 +1,277 @@
+use std::{cell::RefCell, collections::HashMap, rc::Rc};
+
+use crate::error::{Error, Result};
+
+use super::{AudioBackend, Change, Device, Flow, Role, Session, Target};
+
+/// An audio stack that only exists in memory, to run mappings and actions
+/// against without any audio hardware or operating system support. Devices
+/// and sessions are added and removed by hand, which is reported through
+/// `update` like a real backend would.
+#[derive(Default)]
+pub struct MockBackend {
+    devices: Vec<Device>,
+    sessions: Vec<Session>,
+    defaults: HashMap<(Flow, Role), String>,
+    /// Volume and mute, for every device and session
+    levels: HashMap<Target, (f32, bool)>,
+    pending: Vec<Change>,
+    on_change: Option<Box<dyn Fn() + Send + Sync>>,
+}
+
+impl MockBackend {
+    /// `on_change` gets called whenever there is something for `update` to
+    /// pick up.
+    pub fn new<F>(on_change: F) -> Self
+    where
+        F: Fn() + Send + Sync + 'static,
+    {
+        Self {
+            on_change: Some(Box::new(on_change)),
+            ..Self::default()
+        }
+    }
+
+    /// Adds an active device at full volume, unmuted.
+    pub fn add_device(&mut self, id: &str, name: &str, flow: Flow) {
+        self.devices.push(Device {
+            id: id.to_string(),
+            name: name.to_string(),
+            flow,
+            active: true,
+        });
+        self.levels
+            .insert(Target::Device(id.to_string()), (1.0, false));
+        self.push(Change::DeviceAdded(name.to_string()));
+    }
+
+    /// Removes a device along with its sessions.
+    pub fn remove_device(&mut self, id: &str) -> Result<()> {
+        let device = self.device(id)?.clone();
+        for session in self.device_sessions(id) {
+            self.remove_session(&session.id)?;
+        }
+        self.devices.retain(|device| device.id != id);
+        self.defaults.retain(|_, default| default != id);
+        self.levels.remove(&Target::Device(id.to_string()));
+        self.push(Change::DeviceRemoved(device.name));
+        Ok(())
+    }
+
+    /// Like unplugging a device without it disappearing, which also ends its
+    /// sessions.
+    pub fn set_device_active(&mut self, id: &str, active: bool) -> Result<()> {
+        if !active {
+            for session in self.device_sessions(id) {
+                self.remove_session(&session.id)?;
+            }
+        }
+        let device = self
+            .devices
+            .iter_mut()
+            .find(|device| device.id == id)
+            .ok_or_else(|| Error::AudioTargetNotFound(Target::Device(id.to_string())))?;
+        device.active = active;
+        let change = Change::DeviceState {
+            device: device.name.clone(),
+            state: if active { "Active" } else { "Unplugged" }.to_string(),
+        };
+        self.push(change);
+        Ok(())
+    }
+
+    /// Adds a session at full volume, unmuted, to an active device.
+    pub fn add_session(
+        &mut self,
+        device_id: &str,
+        id: &str,
+        process_name: Option<&str>,
+        pid: u32,
+    ) -> Result<()> {
+        let device = self.device(device_id)?.name.clone();
+        let name = process_name.unwrap_or("Unknown").to_string();
+        self.sessions.push(Session {
+            id: id.to_string(),
+            device_id: device_id.to_string(),
+            name: name.clone(),
+            process_name: process_name.map(str::to_string),
+            pid,
+        });
+        self.levels
+            .insert(Target::Session(id.to_string()), (1.0, false));
+        self.push(Change::SessionCreated {
+            device,
+            session: name,
+        });
+        Ok(())
+    }
+
+    pub fn remove_session(&mut self, id: &str) -> Result<()> {
+        let session = self.session(id)?.clone();
+        let device = self.device(&session.device_id)?.name.clone();
+        self.sessions.retain(|session| session.id != id);
+        self.levels.remove(&Target::Session(id.to_string()));
+        self.push(Change::SessionRemoved {
+            device,
+            session: session.name,
+        });
+        Ok(())
+    }
+
+    /// As if the user muted or unmuted a session somewhere else, e.g. in the
+    /// volume mixer.
+    pub fn mute_externally(&mut self, id: &str, mute: bool) -> Result<()> {
+        let target = Target::Session(id.to_string());
+        if self.muted(&target)? == mute {
+            return Ok(());
+        }
+        self.levels.get_mut(&target).unwrap().1 = mute;
+        let session = self.session(id)?;
+        let change = Change::Mute {
+            device: self.device(&session.device_id)?.name.clone(),
+            session: session.name.clone(),
+            mute,
+        };
+        self.push(change);
+        Ok(())
+    }
+
+    fn device(&self, id: &str) -> Result<&Device> {
+        self.devices
+            .iter()
+            .find(|device| device.id == id)
+            .ok_or_else(|| Error::AudioTargetNotFound(Target::Device(id.to_string())))
+    }
+
+    fn session(&self, id: &str) -> Result<&Session> {
+        self.sessions
+            .iter()
+            .find(|session| session.id == id)
+            .ok_or_else(|| Error::AudioTargetNotFound(Target::Session(id.to_string())))
+    }
+
+    fn device_sessions(&self, device_id: &str) -> Vec<Session> {
+        self.sessions
+            .iter()
+            .filter(|session| session.device_id == device_id)
+            .cloned()
+            .collect()
+    }
+
+    fn level(&self, target: &Target) -> Result<&(f32, bool)> {
+        self.levels
+            .get(target)
+            .ok_or_else(|| Error::AudioTargetNotFound(target.clone()))
+    }
+
+    fn level_mut(&mut self, target: &Target) -> Result<&mut (f32, bool)> {
+        self.levels
+            .get_mut(target)
+            .ok_or_else(|| Error::AudioTargetNotFound(target.clone()))
+    }
+
+    fn push(&mut self, change: Change) {
+        self.pending.push(change);
+        if let Some(on_change) = &self.on_change {
+            on_change();
+        }
+    }
+}
+
+impl AudioBackend for MockBackend {
+    fn update(&mut self) -> Result<Vec<Change>> {
+        Ok(std::mem::take(&mut self.pending))
+    }
+
+    fn devices(&self) -> Vec<Device> {
+        self.devices.clone()
+    }
+
+    fn sessions(&self) -> Vec<Session> {
+        self.sessions
+            .iter()
+            .filter(|session| {
+                self.device(&session.device_id)
+                    .is_ok_and(|device| device.active && device.flow == Flow::Render)
+            })
+            .cloned()
+            .collect()
+    }
+
+    fn default_device(&self, flow: Flow, role: Role) -> Option<Device> {
+        let id = self.defaults.get(&(flow, role))?;
+        self.device(id).ok().cloned()
+    }
+
+    fn set_default_device(&mut self, id: &str, role: Role) -> Result<()> {
+        let device = self.device(id)?.clone();
+        if self.defaults.get(&(device.flow, role)) == Some(&device.id) {
+            return Ok(());
+        }
+        self.defaults.insert((device.flow, role), device.id);
+        self.push(Change::DefaultDevice {
+            flow: device.flow,
+            role,
+            device: device.name,
+        });
+        Ok(())
+    }
+
+    fn volume(&self, target: &Target) -> Result<f32> {
+        Ok(self.level(target)?.0)
+    }
+
+    fn set_volume(&mut self, target: &Target, volume: f32) -> Result<()> {
+        self.level_mut(target)?.0 = volume;
+        Ok(())
+    }
+
+    fn muted(&self, target: &Target) -> Result<bool> {
+        Ok(self.level(target)?.1)
+    }
+
+    fn set_muted(&mut self, target: &Target, muted: bool) -> Result<()> {
+        self.level_mut(target)?.1 = muted;
+        Ok(())
+    }
+}
+
+/// So that a test can keep a handle on the mock to script changes with, and
+/// to check the outcome on, while `Audio` owns it.
+impl AudioBackend for Rc<RefCell<MockBackend>> {
+    fn update(&mut self) -> Result<Vec<Change>> {
+        self.borrow_mut().update()
+    }
+
+    fn devices(&self) -> Vec<Device> {
+        self.borrow().devices()
+    }
+
+    fn sessions(&self) -> Vec<Session> {
+        self.borrow().sessions()
+    }
+
+    fn default_device(&self, flow: Flow, role: Role) -> Option<Device> {
+        self.borrow().default_device(flow, role)
+    }
+
+    fn set_default_device(&mut self, id: &str, role: Role) -> Result<()> {
+        self.borrow_mut().set_default_device(id, role)
+    }
+
+    fn volume(&self, target: &Target) -> Result<f32> {
+        self.borrow().volume(target)
+    }
+
+    fn set_volume(&mut self, target: &Target, volume: f32) -> Result<()> {
+        self.borrow_mut().set_volume(target, volume)
+    }
+
+    fn muted(&self, target: &Target) -> Result<bool> {
+        self.borrow().muted(target)
+    }
+
+    fn set_muted(&mut self, target: &Target, muted: bool) -> Result<()> {
+        self.borrow_mut().set_muted(target, muted)
+    }
+}
@@ -16,7 +16,7 @@ fn main() -> Result<()> {
     let outport = outports.iter().find(|port| {
         midi_out
             .port_name(port)
-            .map_or(false, |name| name == "X-TOUCH MINI")
+            .is_ok_and(|name| name == "X-TOUCH MINI")
     });
     println!("outport");
     let Some(outport) = outport else {
@@ -32,7 +32,7 @@ fn main() -> Result<()> {
     let inport = inports.iter().find(|port| {
         midi_in
             .port_name(port)
-            .map_or(false, |name| name == "X-TOUCH MINI")
+            .is_ok_and(|name| name == "X-TOUCH MINI")
     });
     let Some(inport) = inport else {
         return Err("No input port found".into());
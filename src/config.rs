@@ -0,0 +1,1009 @@
+use std::{cell::Cell, fs, path::Path};
+
+use log::warn;
+use midly::{
+    live::MtcQuarterFrameMessage,
+    num::{u14, u4, u7},
+};
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+use serde::{
+    de::{
+        self, value::MapAccessDeserializer, DeserializeSeed, EnumAccess, IntoDeserializer,
+        MapAccess, VariantAccess, Visitor,
+    },
+    Deserialize, Deserializer,
+};
+use wildmatch::WildMatch;
+
+use crate::{
+    controls::{button::ButtonSource, trigger::SysExPattern, ControlType},
+    error::{Error, Result},
+};
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct Config {
+    pub(crate) input: InputConfig,
+    /// Where to send feedback for indicators, none is sent without this
+    #[serde(default)]
+    pub(crate) output: Option<OutputConfig>,
+    #[serde(default)]
+    pub(crate) controls: Vec<ControlType>,
+    #[serde(default)]
+    pub(crate) layers: Vec<LayerConfig>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct InputConfig {
+    pub(crate) port: String,
+    /// Lots of controllers send note on with velocity 0 instead of note off,
+    /// so that running status saves them a byte. With this on, which is the
+    /// default, those are handled as the note off they stand for.
+    #[serde(default = "InputConfig::default_treat_zero_velocity_as_note_off")]
+    pub(crate) treat_zero_velocity_as_note_off: bool,
+}
+
+impl InputConfig {
+    fn default_treat_zero_velocity_as_note_off() -> bool {
+        true
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct OutputConfig {
+    pub(crate) port: String,
+}
+
+/// A second set of controls that takes over from the main ones while it is
+/// active, like a shift key. Messages that none of its controls handle still
+/// go to the layers below it, and from there to the main controls.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct LayerConfig {
+    /// Toggled layers stay active across reloads as long as this is the same
+    pub(crate) name: String,
+    /// Activates the layer while held. Its messages go to the layer only, and
+    /// its LED is lit while the layer is active.
+    pub(crate) button: ButtonSource,
+    /// Activate and deactivate the layer with a press each instead
+    #[serde(default)]
+    pub(crate) toggle: bool,
+    #[serde(default)]
+    pub(crate) controls: Vec<ControlType>,
+}
+
+impl Config {
+    pub(crate) fn load(path: &Path) -> Result<Self> {
+        let contents = fs::read_to_string(path)?;
+        Self::parse(&contents, path)
+    }
+
+    /// `path` is only for pointing at it in errors
+    fn parse(contents: &str, path: &Path) -> Result<Self> {
+        toml::from_str(contents).map_err(|error| {
+            // toml gives us a byte range, turn it into something a human can
+            // find in their editor
+            let before = &contents[..error.span().map_or(0, |span| span.start)];
+            let line = before.matches('\n').count() + 1;
+            let column = before.len() - before.rfind('\n').map_or(0, |i| i + 1) + 1;
+            Error::Config {
+                path: path.to_path_buf(),
+                line,
+                column,
+                message: error.message().to_string(),
+            }
+        })
+    }
+}
+
+/// Calls `on_change` whenever the file at `path` is written to or replaced.
+/// The watch stops when the returned watcher is dropped.
+pub(crate) fn watch<F>(path: &Path, on_change: F) -> Result<RecommendedWatcher>
+where
+    F: Fn() + Send + 'static,
+{
+    // Plenty of editors save by writing a new file and renaming it over the
+    // old one, which would end a watch on the file itself. So watch the
+    // directory instead and pick out the events for our file.
+    let path = path.canonicalize()?;
+    let directory = path.parent().unwrap_or(&path).to_path_buf();
+    let mut watcher =
+        notify::recommended_watcher(move |result: notify::Result<notify::Event>| match result {
+            Ok(event) => {
+                if (event.kind.is_create() || event.kind.is_modify()) && event.paths.contains(&path)
+                {
+                    on_change();
+                }
+            }
+            Err(error) => warn!("Error watching mapping file: {error}"),
+        })?;
+    watcher.watch(&directory, RecursiveMode::NonRecursive)?;
+    Ok(watcher)
+}
+
+/// Implements `Deserialize` for an enum that is picked by its `$tag` field,
+/// next to the variant's own fields, like `#[serde(tag = "..")]` does. Serde's
+/// own version reads the whole table before it looks at the tag, and loses
+/// track of where in the file the fields were on the way, so that errors end
+/// up pointing at the start of the table rather than at the mistake. This one
+/// hands the fields after the tag straight to the variant.
+///
+/// The variants themselves are left to `#[derive(Deserialize)]` with
+/// `#[serde(remote = "Self")]`, which keeps the derived code out of the way as
+/// an inherent `deserialize`.
+macro_rules! tagged_enum {
+    ($enum:ident, $tag:literal) => {
+        impl<'de> serde::Deserialize<'de> for $enum {
+            fn deserialize<D>(deserializer: D) -> core::result::Result<Self, D::Error>
+            where
+                D: serde::Deserializer<'de>,
+            {
+                struct Visitor;
+
+                impl<'de> serde::de::Visitor<'de> for Visitor {
+                    type Value = $enum;
+
+                    fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+                        write!(formatter, "a table with a `{}`", $tag)
+                    }
+
+                    fn visit_map<A>(self, map: A) -> core::result::Result<$enum, A::Error>
+                    where
+                        A: serde::de::MapAccess<'de>,
+                    {
+                        let names =
+                            $crate::config::variant_names(|names| $enum::deserialize(names));
+                        $enum::deserialize($crate::config::TaggedFields::new(map, $tag, names)?)
+                    }
+                }
+
+                deserializer.deserialize_map(Visitor)
+            }
+        }
+    };
+}
+
+pub(crate) use tagged_enum;
+
+/// The names of the variants of an enum, as its derived `deserialize` would
+/// accept them.
+pub(crate) fn variant_names<T, F>(deserialize: F) -> &'static [&'static str]
+where
+    F: FnOnce(VariantNames) -> core::result::Result<T, de::value::Error>,
+{
+    let names = Cell::new(&[][..]);
+    // Fails by design, the names are all we are after
+    let _ = deserialize(VariantNames(&names));
+    names.get()
+}
+
+/// Stands in for the input to a derived `deserialize`, to catch the names of
+/// the variants it hands over when it asks for an enum.
+pub(crate) struct VariantNames<'a>(&'a Cell<&'static [&'static str]>);
+
+impl<'de> Deserializer<'de> for VariantNames<'_> {
+    type Error = de::value::Error;
+
+    fn deserialize_any<V>(self, _visitor: V) -> core::result::Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        Err(de::Error::custom("expected an enum"))
+    }
+
+    fn deserialize_enum<V>(
+        self,
+        _name: &'static str,
+        variants: &'static [&'static str],
+        _visitor: V,
+    ) -> core::result::Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        self.0.set(variants);
+        Err(de::Error::custom(
+            "only the names of the variants are known",
+        ))
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf option unit unit_struct newtype_struct seq tuple
+        tuple_struct map struct identifier ignored_any
+    }
+}
+
+/// The variant picked by a tag, and the fields of the table other than the
+/// tag, see `tagged_enum!`. It poses as an enum to the derived `deserialize`.
+pub(crate) struct TaggedFields<A> {
+    name: &'static str,
+    /// The ones that came before the tag, and had to be read to find it
+    before_tag: std::vec::IntoIter<(String, toml::Value)>,
+    /// The value of the key from `before_tag` that was handed out last
+    value: Option<toml::Value>,
+    after_tag: A,
+}
+
+impl<'de, A> TaggedFields<A>
+where
+    A: MapAccess<'de>,
+{
+    /// Reads `map` up to and including the tag, which has to be one of
+    /// `names`
+    pub(crate) fn new(
+        mut map: A,
+        tag: &'static str,
+        names: &'static [&'static str],
+    ) -> core::result::Result<Self, A::Error> {
+        let mut before_tag = Vec::new();
+        while let Some(key) = map.next_key::<String>()? {
+            if key == tag {
+                return Ok(Self {
+                    name: map.next_value_seed(TagName(names))?,
+                    before_tag: before_tag.into_iter(),
+                    value: None,
+                    after_tag: map,
+                });
+            }
+            before_tag.push((key, map.next_value()?));
+        }
+        Err(de::Error::missing_field(tag))
+    }
+}
+
+impl<'de, A> Deserializer<'de> for TaggedFields<A>
+where
+    A: MapAccess<'de>,
+{
+    type Error = A::Error;
+
+    fn deserialize_any<V>(self, visitor: V) -> core::result::Result<V::Value, A::Error>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_enum(self)
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf option unit unit_struct newtype_struct seq tuple
+        tuple_struct map struct enum identifier ignored_any
+    }
+}
+
+impl<'de, A> EnumAccess<'de> for TaggedFields<A>
+where
+    A: MapAccess<'de>,
+{
+    type Error = A::Error;
+    type Variant = Self;
+
+    fn variant_seed<V>(self, seed: V) -> core::result::Result<(V::Value, Self), A::Error>
+    where
+        V: DeserializeSeed<'de>,
+    {
+        let variant = seed.deserialize(self.name.into_deserializer())?;
+        Ok((variant, self))
+    }
+}
+
+impl<'de, A> VariantAccess<'de> for TaggedFields<A>
+where
+    A: MapAccess<'de>,
+{
+    type Error = A::Error;
+
+    fn unit_variant(mut self) -> core::result::Result<(), A::Error> {
+        match self.next_key::<String>()? {
+            Some(key) => Err(de::Error::unknown_field(&key, &[])),
+            None => Ok(()),
+        }
+    }
+
+    fn newtype_variant_seed<T>(self, seed: T) -> core::result::Result<T::Value, A::Error>
+    where
+        T: DeserializeSeed<'de>,
+    {
+        seed.deserialize(MapAccessDeserializer::new(self))
+    }
+
+    fn tuple_variant<V>(self, _len: usize, _visitor: V) -> core::result::Result<V::Value, A::Error>
+    where
+        V: Visitor<'de>,
+    {
+        Err(de::Error::custom("tuple variants can't be picked by a tag"))
+    }
+
+    fn struct_variant<V>(
+        self,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> core::result::Result<V::Value, A::Error>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_map(self)
+    }
+}
+
+impl<'de, A> MapAccess<'de> for TaggedFields<A>
+where
+    A: MapAccess<'de>,
+{
+    type Error = A::Error;
+
+    fn next_key_seed<K>(&mut self, seed: K) -> core::result::Result<Option<K::Value>, A::Error>
+    where
+        K: DeserializeSeed<'de>,
+    {
+        let Some((key, value)) = self.before_tag.next() else {
+            return self.after_tag.next_key_seed(seed);
+        };
+        self.value = Some(value);
+        seed.deserialize(IntoDeserializer::<A::Error>::into_deserializer(key))
+            .map(Some)
+    }
+
+    fn next_value_seed<V>(&mut self, seed: V) -> core::result::Result<V::Value, A::Error>
+    where
+        V: DeserializeSeed<'de>,
+    {
+        match self.value.take() {
+            Some(value) => seed.deserialize(value).map_err(de::Error::custom),
+            None => self.after_tag.next_value_seed(seed),
+        }
+    }
+}
+
+/// Reads the value of a tag, checking it against the names of the variants
+/// while toml still knows where it is.
+struct TagName(&'static [&'static str]);
+
+impl<'de> DeserializeSeed<'de> for TagName {
+    type Value = &'static str;
+
+    fn deserialize<D>(self, deserializer: D) -> core::result::Result<Self::Value, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let name = String::deserialize(deserializer)?;
+        self.0
+            .iter()
+            .find(|known| **known == name)
+            .copied()
+            .ok_or_else(|| de::Error::unknown_variant(&name, self.0))
+    }
+}
+
+fn ranged<'de, D>(
+    deserializer: D,
+    what: &str,
+    min: i64,
+    max: i64,
+) -> core::result::Result<i64, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let value = i64::deserialize(deserializer)?;
+    if (min..=max).contains(&value) {
+        Ok(value)
+    } else {
+        Err(de::Error::custom(format!(
+            "{what} {value} is out of range ({min}-{max})"
+        )))
+    }
+}
+
+/// The ways to write down a set of numbers, see `ranged_set`.
+#[derive(Deserialize)]
+#[serde(
+    untagged,
+    expecting = "expected a number, a list of numbers, a `{ low, high }` range or \"any\""
+)]
+enum NumberSet {
+    One(i64),
+    List(Vec<i64>),
+    Range { low: i64, high: i64 },
+    Any(String),
+}
+
+/// Like `ranged`, for fields that can cover several numbers at once: a single
+/// number, a list of them, an inclusive `{ low = .., high = .. }` range, or
+/// "any" for all of them. Comes back sorted and without duplicates.
+fn ranged_set<'de, D>(
+    deserializer: D,
+    what: &str,
+    min: i64,
+    max: i64,
+) -> core::result::Result<Vec<i64>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let check = |value: i64| {
+        if (min..=max).contains(&value) {
+            Ok(value)
+        } else {
+            Err(de::Error::custom(format!(
+                "{what} {value} is out of range ({min}-{max})"
+            )))
+        }
+    };
+    let mut values = match NumberSet::deserialize(deserializer)? {
+        NumberSet::One(value) => vec![check(value)?],
+        NumberSet::List(values) => values
+            .into_iter()
+            .map(check)
+            .collect::<core::result::Result<_, _>>()?,
+        NumberSet::Range { low, high } if low > high => {
+            return Err(de::Error::custom(format!(
+                "{what} range is empty, `low` {low} is above `high` {high}"
+            )))
+        }
+        NumberSet::Range { low, high } => (check(low)?..=check(high)?).collect(),
+        NumberSet::Any(any) if any == "any" => (min..=max).collect(),
+        NumberSet::Any(other) => {
+            return Err(de::Error::invalid_value(
+                de::Unexpected::Str(&other),
+                &"\"any\"",
+            ))
+        }
+    };
+    if values.is_empty() {
+        return Err(de::Error::custom(format!("no {what} to match on")));
+    }
+    values.sort_unstable();
+    values.dedup();
+    Ok(values)
+}
+
+// The casts below can't truncate, `ranged` and `ranged_set` have already
+// checked the bounds.
+
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+pub(crate) fn channel<'de, D>(deserializer: D) -> core::result::Result<u4, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    ranged(deserializer, "channel", 0, 15).map(|value| u4::new(value as u8))
+}
+
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+pub(crate) fn channels<'de, D>(deserializer: D) -> core::result::Result<Vec<u4>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    ranged_set(deserializer, "channel", 0, 15).map(|values| {
+        values
+            .into_iter()
+            .map(|value| u4::new(value as u8))
+            .collect()
+    })
+}
+
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+pub(crate) fn u4<'de, D>(deserializer: D) -> core::result::Result<u4, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    ranged(deserializer, "4-bit value", 0, 15).map(|value| u4::new(value as u8))
+}
+
+pub(crate) fn optional_u4<'de, D>(deserializer: D) -> core::result::Result<Option<u4>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    u4(deserializer).map(Some)
+}
+
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+pub(crate) fn u7<'de, D>(deserializer: D) -> core::result::Result<u7, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    ranged(deserializer, "7-bit value", 0, 127).map(|value| u7::new(value as u8))
+}
+
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+pub(crate) fn u7s<'de, D>(deserializer: D) -> core::result::Result<Vec<u7>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    ranged_set(deserializer, "7-bit value", 0, 127).map(|values| {
+        values
+            .into_iter()
+            .map(|value| u7::new(value as u8))
+            .collect()
+    })
+}
+
+pub(crate) fn optional_u7<'de, D>(deserializer: D) -> core::result::Result<Option<u7>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    u7(deserializer).map(Some)
+}
+
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+pub(crate) fn u14<'de, D>(deserializer: D) -> core::result::Result<u14, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    ranged(deserializer, "14-bit value", 0, 16383).map(|value| u14::new(value as u16))
+}
+
+pub(crate) fn optional_u14<'de, D>(deserializer: D) -> core::result::Result<Option<u14>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    u14(deserializer).map(Some)
+}
+
+#[allow(clippy::cast_possible_truncation)]
+pub(crate) fn pitch_bend<'de, D>(deserializer: D) -> core::result::Result<i16, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    ranged(deserializer, "pitch bend", -8192, 8191).map(|value| value as i16)
+}
+
+pub(crate) fn optional_pitch_bend<'de, D>(
+    deserializer: D,
+) -> core::result::Result<Option<i16>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    pitch_bend(deserializer).map(Some)
+}
+
+pub(crate) fn mtc_quarter_frame_message<'de, D>(
+    deserializer: D,
+) -> core::result::Result<MtcQuarterFrameMessage, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    const VARIANTS: &[&str] = &[
+        "frames_low",
+        "frames_high",
+        "seconds_low",
+        "seconds_high",
+        "minutes_low",
+        "minutes_high",
+        "hours_low",
+        "hours_high",
+    ];
+    let name = String::deserialize(deserializer)?;
+    Ok(match name.as_str() {
+        "frames_low" => MtcQuarterFrameMessage::FramesLow,
+        "frames_high" => MtcQuarterFrameMessage::FramesHigh,
+        "seconds_low" => MtcQuarterFrameMessage::SecondsLow,
+        "seconds_high" => MtcQuarterFrameMessage::SecondsHigh,
+        "minutes_low" => MtcQuarterFrameMessage::MinutesLow,
+        "minutes_high" => MtcQuarterFrameMessage::MinutesHigh,
+        "hours_low" => MtcQuarterFrameMessage::HoursLow,
+        "hours_high" => MtcQuarterFrameMessage::HoursHigh,
+        _ => return Err(de::Error::unknown_variant(&name, VARIANTS)),
+    })
+}
+
+#[derive(Deserialize)]
+#[serde(untagged, expecting = "expected a number or a list of 3 numbers")]
+enum ManufacturerId {
+    One(i64),
+    Three(Vec<i64>),
+}
+
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+pub(crate) fn manufacturer_id<'de, D>(deserializer: D) -> core::result::Result<Vec<u7>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let bytes = match ManufacturerId::deserialize(deserializer)? {
+        ManufacturerId::One(0) => {
+            return Err(de::Error::custom(
+                "manufacturer IDs starting with 0 are 3 bytes long",
+            ))
+        }
+        ManufacturerId::One(byte) => vec![byte],
+        ManufacturerId::Three(bytes) if bytes.len() == 3 && bytes[0] == 0 => bytes,
+        ManufacturerId::Three(_) => {
+            return Err(de::Error::custom(
+                "manufacturer IDs are a single byte, or 3 bytes starting with 0",
+            ))
+        }
+    };
+    bytes
+        .into_iter()
+        .map(|byte| {
+            if (0..=0x7F).contains(&byte) {
+                Ok(u7::new(byte as u8))
+            } else {
+                Err(de::Error::custom(format!(
+                    "manufacturer ID byte {byte} is out of range (0-127)"
+                )))
+            }
+        })
+        .collect()
+}
+
+pub(crate) fn sysex_pattern<'de, D>(deserializer: D) -> core::result::Result<SysExPattern, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let pattern = String::deserialize(deserializer)?;
+    pattern.parse().map_err(de::Error::custom)
+}
+
+/// Names of processes and devices on Windows are case insensitive, so
+/// patterns for them are too
+pub(crate) fn name_pattern<'de, D>(deserializer: D) -> core::result::Result<WildMatch, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let pattern = String::deserialize(deserializer)?;
+    Ok(WildMatch::new_case_insensitive(&pattern))
+}
+
+pub(crate) fn name_patterns<'de, D>(
+    deserializer: D,
+) -> core::result::Result<Vec<WildMatch>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let patterns = Vec::<String>::deserialize(deserializer)?;
+    Ok(patterns
+        .iter()
+        .map(|pattern| WildMatch::new_case_insensitive(pattern))
+        .collect())
+}
+
+pub(crate) fn optional_name_pattern<'de, D>(
+    deserializer: D,
+) -> core::result::Result<Option<WildMatch>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    name_pattern(deserializer).map(Some)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::controls::{
+        trigger::{TriggerMidiMessage, ValueMatchType},
+        ControlType,
+    };
+
+    fn parse(contents: &str) -> Result<Config> {
+        Config::parse(contents, Path::new("mappings.toml"))
+    }
+
+    fn error(contents: &str) -> String {
+        parse(contents).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn controls() {
+        let config = parse(
+            r#"[input]
+port = "X-TOUCH MINI"
+
+[[controls]]
+type = "trigger"
+[controls.command]
+message = "note_on"
+channel = 0
+note = 0x59
+velocity = 0x7F
+
+[[controls]]
+type = "trigger"
+auto_indicate = true
+command = { message = "controller", channel = 15, controller = 1, value = 0, match_type = "threshold_or_above" }
+"#,
+        )
+        .unwrap();
+        assert_eq!(config.input.port, "X-TOUCH MINI");
+        let [ControlType::Trigger(first), ControlType::Trigger(second)] = &config.controls[..]
+        else {
+            panic!("{:?}", config.controls);
+        };
+        assert!(matches!(
+            &first.command,
+            TriggerMidiMessage::NoteOn(note_on) if note_on.note == [u7::new(0x59)] && note_on.velocity == 0x7F
+        ));
+        assert!(second.auto_indicate);
+        assert!(matches!(
+            &second.command,
+            TriggerMidiMessage::Controller(controller)
+                if controller.channel == [u4::new(15)] && controller.match_type == ValueMatchType::ThresholdOrAbove
+        ));
+    }
+
+    #[test]
+    fn example() {
+        Config::load(Path::new("mappings.toml")).unwrap();
+    }
+
+    #[test]
+    fn tag_after_the_fields() {
+        let config = parse(
+            r#"[input]
+port = "X-TOUCH MINI"
+
+[[controls]]
+command = { channel = 0, note = 0x59, velocity = 0x7F, message = "note_on" }
+type = "trigger"
+"#,
+        )
+        .unwrap();
+        assert_eq!(config.controls.len(), 1);
+    }
+
+    #[test]
+    fn errors_point_at_the_value() {
+        let message = error(
+            r#"[input]
+port = "X-TOUCH MINI"
+
+[[controls]]
+type = "trigger"
+[controls.command]
+message = "note_on"
+channel = 16
+note = 0x59
+velocity = 0x7F
+"#,
+        );
+        assert_eq!(
+            message,
+            "mappings.toml:8:11: channel 16 is out of range (0-15)"
+        );
+        let message = error(
+            r#"[input]
+port = "X-TOUCH MINI"
+
+[[controls]]
+type = "trigger"
+command = { message = "note_on", channel = 0, note = 0x80, velocity = 0x7F }
+"#,
+        );
+        assert_eq!(
+            message,
+            "mappings.toml:6:54: 7-bit value 128 is out of range (0-127)"
+        );
+    }
+
+    #[test]
+    fn unknown_kinds() {
+        let message = error(
+            r#"[input]
+port = "X-TOUCH MINI"
+
+[[controls]]
+type = "trigger"
+command = { message = "note", channel = 0, note = 0x59, velocity = 0x7F }
+"#,
+        );
+        assert!(
+            message.starts_with("mappings.toml:6:23: unknown variant `note`, expected one of"),
+            "{message}"
+        );
+        let message = error(
+            r#"[input]
+port = "X-TOUCH MINI"
+
+[[controls]]
+type = "fader"
+"#,
+        );
+        assert!(
+            message.starts_with(
+                "mappings.toml:5:8: unknown variant `fader`, expected one of `trigger`"
+            ),
+            "{message}"
+        );
+        let message = error(
+            r#"[input]
+port = "X-TOUCH MINI"
+
+[[controls]]
+type = "trigger"
+command = { message = "tune_request", channel = 0 }
+"#,
+        );
+        assert!(
+            message.contains("unknown field `channel`, there are no fields"),
+            "{message}"
+        );
+    }
+
+    #[test]
+    fn missing_tag() {
+        let message = error(
+            r#"[input]
+port = "X-TOUCH MINI"
+
+[[controls]]
+command = { message = "note_on", channel = 0, note = 0x59, velocity = 0x7F }
+"#,
+        );
+        assert!(message.ends_with("missing field `type`"), "{message}");
+    }
+
+    #[test]
+    fn errors_in_struct_variants() {
+        let message = error(
+            r#"[input]
+port = "X-TOUCH MINI"
+
+[[controls]]
+type = "absolute_value"
+[controls.command]
+message = "pitch_bend"
+channel = 16
+"#,
+        );
+        assert_eq!(
+            message,
+            "mappings.toml:8:11: channel 16 is out of range (0-15)"
+        );
+        let message = error(
+            r#"[input]
+port = "X-TOUCH MINI"
+
+[[controls]]
+type = "absolute_value"
+command = { message = "pitch_bend", channel = 8, controller = 1 }
+"#,
+        );
+        assert!(
+            message.starts_with("mappings.toml:6:50: unknown field `controller`"),
+            "{message}"
+        );
+    }
+
+    #[test]
+    fn syntax_errors() {
+        let message = error("[input]\nport = X-TOUCH MINI\n");
+        assert!(message.starts_with("mappings.toml:2:8: "), "{message}");
+    }
+
+    /// A directory of its own for each test, as they run in parallel
+    fn temp_dir(name: &str) -> std::path::PathBuf {
+        let directory = std::env::temp_dir().join(format!(
+            "midi-windows-controller-{}-{name}",
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&directory);
+        fs::create_dir_all(&directory).unwrap();
+        directory
+    }
+
+    fn watched(path: &Path) -> (RecommendedWatcher, std::sync::mpsc::Receiver<()>) {
+        let (tx, rx) = std::sync::mpsc::channel();
+        let watcher = watch(path, move || {
+            let _ = tx.send(());
+        })
+        .unwrap();
+        (watcher, rx)
+    }
+
+    #[test]
+    fn watch_sees_writes() {
+        let directory = temp_dir("writes");
+        let path = directory.join("mappings.toml");
+        fs::write(&path, "old").unwrap();
+        let (_watcher, rx) = watched(&path);
+        fs::write(&path, "new").unwrap();
+        rx.recv_timeout(Duration::from_secs(5)).unwrap();
+        fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn watch_sees_replacements() {
+        let directory = temp_dir("replacements");
+        let path = directory.join("mappings.toml");
+        fs::write(&path, "old").unwrap();
+        let (_watcher, rx) = watched(&path);
+        let new_path = directory.join("mappings.toml.new");
+        fs::write(&new_path, "new").unwrap();
+        fs::rename(&new_path, &path).unwrap();
+        rx.recv_timeout(Duration::from_secs(5)).unwrap();
+        fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn watch_ignores_other_files() {
+        let directory = temp_dir("other-files");
+        let path = directory.join("mappings.toml");
+        fs::write(&path, "old").unwrap();
+        let (_watcher, rx) = watched(&path);
+        fs::write(directory.join("other.toml"), "other").unwrap();
+        assert!(rx.recv_timeout(Duration::from_millis(500)).is_err());
+        fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[derive(Debug, Deserialize)]
+    struct Sets {
+        #[serde(default, deserialize_with = "channels")]
+        channel: Vec<u4>,
+        #[serde(default, deserialize_with = "u7s")]
+        note: Vec<u7>,
+    }
+
+    fn sets(toml: &str) -> core::result::Result<Sets, String> {
+        toml::from_str(toml).map_err(|error| error.message().to_string())
+    }
+
+    fn notes(toml: &str) -> Vec<u8> {
+        sets(toml)
+            .unwrap()
+            .note
+            .into_iter()
+            .map(u7::as_int)
+            .collect()
+    }
+
+    #[test]
+    fn single_number() {
+        assert_eq!(notes("note = 0x24"), [0x24]);
+    }
+
+    #[test]
+    fn list_is_sorted_without_duplicates() {
+        assert_eq!(notes("note = [0x26, 0x24, 0x26]"), [0x24, 0x26]);
+    }
+
+    #[test]
+    fn range() {
+        assert_eq!(
+            notes("note = { low = 0x24, high = 0x27 }"),
+            [0x24, 0x25, 0x26, 0x27]
+        );
+        assert_eq!(notes("note = { low = 0x24, high = 0x24 }"), [0x24]);
+    }
+
+    #[test]
+    fn any() {
+        let channels = sets(r#"channel = "any""#).unwrap().channel;
+        assert_eq!(channels, (0..16).map(u4::new).collect::<Vec<_>>());
+        assert_eq!(notes(r#"note = "any""#).len(), 128);
+    }
+
+    #[test]
+    fn out_of_range() {
+        let error = sets("channel = 16").unwrap_err();
+        assert!(
+            error.contains("channel 16 is out of range (0-15)"),
+            "{error}"
+        );
+        let error = sets("channel = [1, 16]").unwrap_err();
+        assert!(error.contains("channel 16 is out of range"), "{error}");
+        let error = sets("note = { low = 0x70, high = 0x80 }").unwrap_err();
+        assert!(error.contains("7-bit value 128 is out of range"), "{error}");
+    }
+
+    #[test]
+    fn backwards_range() {
+        let error = sets("note = { low = 10, high = 5 }").unwrap_err();
+        assert!(error.contains("`low` 10 is above `high` 5"), "{error}");
+    }
+
+    #[test]
+    fn empty_list() {
+        let error = sets("channel = []").unwrap_err();
+        assert!(error.contains("no channel to match on"), "{error}");
+    }
+
+    #[test]
+    fn anything_else() {
+        let error = sets(r#"channel = "all""#).unwrap_err();
+        assert!(error.contains(r#"expected "any""#), "{error}");
+        let error = sets("channel = 1.5").unwrap_err();
+        assert!(error.contains("expected a number, a list"), "{error}");
+    }
+}
@@ -0,0 +1,116 @@
+use std::collections::{HashMap, HashSet};
+
+use log::warn;
+use midir::MidiOutputConnection;
+use midly::live::LiveEvent;
+
+use crate::{audio::Audio, controls::button::ButtonSource, MidiBytes};
+
+/// State that controls share, and that outlives any particular set of
+/// mappings.
+pub(crate) struct Context {
+    output: Option<MidiOutputConnection>,
+    pub(crate) audio: Audio,
+    /// The state of latching buttons, by name. Kept here rather than in the
+    /// controls, so that it survives reloading the config.
+    latches: HashMap<String, bool>,
+    /// The selected member of each radio group, by group name
+    selections: HashMap<String, String>,
+    /// Radio groups whose selection changed since the last
+    /// `take_changed_groups`
+    changed_groups: Vec<String>,
+    /// The layers that are switched on, by name
+    active_layers: HashSet<String>,
+    /// Keys of chords that fired and haven't been let go of completely yet,
+    /// which the controls of the keys themselves should leave alone
+    chord_keys: HashSet<ButtonSource>,
+    /// Everything that went through `send`, for tests to check the feedback
+    #[cfg(test)]
+    pub(crate) sent: Vec<MidiBytes>,
+}
+
+impl Context {
+    pub(crate) fn new(output: Option<MidiOutputConnection>, audio: Audio) -> Self {
+        Self {
+            output,
+            audio,
+            latches: HashMap::new(),
+            selections: HashMap::new(),
+            changed_groups: Vec::new(),
+            active_layers: HashSet::new(),
+            chord_keys: HashSet::new(),
+            #[cfg(test)]
+            sent: Vec::new(),
+        }
+    }
+
+    /// Latches start out off
+    pub(crate) fn latched(&self, name: &str) -> bool {
+        self.latches.get(name).copied().unwrap_or_default()
+    }
+
+    /// Returns the new state
+    pub(crate) fn flip_latch(&mut self, name: &str) -> bool {
+        let on = self.latches.entry(name.to_owned()).or_default();
+        *on = !*on;
+        *on
+    }
+
+    /// Nothing is selected until one of the group's buttons is pressed
+    pub(crate) fn selected(&self, group: &str) -> Option<&str> {
+        self.selections.get(group).map(String::as_str)
+    }
+
+    pub(crate) fn select(&mut self, group: &str, member: &str) {
+        if self.selected(group) == Some(member) {
+            return;
+        }
+        self.selections.insert(group.to_owned(), member.to_owned());
+        self.changed_groups.push(group.to_owned());
+    }
+
+    pub(crate) fn layer_active(&self, name: &str) -> bool {
+        self.active_layers.contains(name)
+    }
+
+    /// Returns whether that changed anything
+    pub(crate) fn set_layer_active(&mut self, name: &str, active: bool) -> bool {
+        if active {
+            self.active_layers.insert(name.to_owned())
+        } else {
+            self.active_layers.remove(name)
+        }
+    }
+
+    pub(crate) fn held_by_chord(&self, key: &ButtonSource) -> bool {
+        self.chord_keys.contains(key)
+    }
+
+    pub(crate) fn set_held_by_chord(&mut self, key: &ButtonSource, held: bool) {
+        if held {
+            self.chord_keys.insert(key.clone());
+        } else {
+            self.chord_keys.remove(key);
+        }
+    }
+
+    /// So that the LEDs of the members that were deselected can be updated
+    pub(crate) fn take_changed_groups(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.changed_groups)
+    }
+
+    /// Sends feedback to the controller, if there is an output port. An LED
+    /// that doesn't light up isn't worth stopping for, so failures are only
+    /// logged.
+    pub(crate) fn send(&mut self, event: &LiveEvent) {
+        let bytes = MidiBytes::from(*event);
+        #[cfg(test)]
+        self.sent.push(bytes.clone());
+        let Some(output) = &mut self.output else {
+            return;
+        };
+        if let Err(error) = output.send(&bytes) {
+            warn!("Failed to send midi message {bytes:?}: {error}");
+        }
+    }
+}
@@ -1,85 +1,234 @@
-use crate::MidiBytes;
+use crate::{
+    actions::{Action, ActionType, ControlValue},
+    config,
+    context::Context,
+    MidiBytes,
+};
+use absolute_value::AbsoluteValue;
+use button::Button;
 use enum_dispatch::enum_dispatch;
+use indicator::Indicator;
 use midly::live::LiveEvent;
-use trigger::TriggerMidiMessage;
+use relative_value::RelativeValue;
+use serde::Deserialize;
+use std::{
+    cell::Cell,
+    time::{Duration, Instant},
+};
+use trigger::{Trigger, TriggerMidiMessage};
 
 pub(crate) mod trigger;
-use trigger::{
-    TriggerActiveSensing, TriggerAftertouch, TriggerChannelAftertouch, TriggerContinue,
-    TriggerController, TriggerMtcQuarterFrame, TriggerNoteOff, TriggerNoteOn, TriggerPitchBend,
-    TriggerProgramChange, TriggerReset, TriggerSongPosition, TriggerSongSelect, TriggerStart,
-    TriggerStop, TriggerTimingClock, TriggerTuneRequest,
-};
+pub(crate) mod absolute_value;
+pub(crate) mod button;
 pub(crate) mod indicator;
-
-//enum Direction {
-//    Up,
-//    Down,
-//}
+pub(crate) mod relative_value;
 
 #[enum_dispatch]
 pub(crate) trait Control {
-    fn handle_midi_event_inner(&self, event: &LiveEvent);
-    fn threshold_hash_key_inner(&self) -> Option<LiveEvent>;
-    fn exact_hash_key_inner(&self) -> Option<LiveEvent>;
+    /// `timestamp` is in microseconds, from an arbitrary starting point
+    fn handle_midi_event(&self, event: &LiveEvent, timestamp: u64, context: &mut Context);
+    fn threshold_hash_keys_inner(&self) -> Vec<LiveEvent<'_>>;
+    fn exact_hash_keys_inner(&self) -> Vec<LiveEvent<'_>>;
+
+    /// Brings any feedback on the controller up to date, e.g. after the
+    /// mappings were (re)loaded
+    fn indicate(&self, _context: &mut Context) {}
 
-    fn handle_midi_event(&self, message: &[u8]) {
-        let event = LiveEvent::parse(message).unwrap();
-        self.handle_midi_event_inner(&event);
+    /// When the control next wants `handle_deadline` to be called, for
+    /// controls that act on time passing as well as on messages
+    fn deadline(&self) -> Option<Instant> {
+        None
     }
-    fn threshold_hash_key(&self) -> Option<MidiBytes> {
-        self.threshold_hash_key_inner().map(Into::into)
+    fn handle_deadline(&self, _now: Instant, _context: &mut Context) {}
+
+    /// Whether the control takes `event` for itself, so that the other
+    /// controls for it get `handle_captured_event` instead, e.g. a chord that
+    /// `event` completes
+    fn captures(&self, _event: &LiveEvent, _timestamp: u64) -> bool {
+        false
+    }
+    /// For keeping track of state that `event` affects, without acting on it.
+    /// Gets the messages that another control captured, or that went to
+    /// another layer.
+    fn handle_captured_event(&self, _event: &LiveEvent, _timestamp: u64, _context: &mut Context) {}
+    /// How long the other controls for `event` have to wait before they get
+    /// it, as the control might still capture it later on, e.g. a key press
+    /// that may become part of a chord. The control itself gets it right away.
+    fn holds_back(&self, _event: &LiveEvent) -> Option<Duration> {
+        None
     }
-    fn exact_hash_key(&self) -> Option<MidiBytes> {
-        self.exact_hash_key_inner().map(Into::into)
+
+    /// The radio group the control is a member of, so that its feedback can
+    /// be updated when another member gets selected
+    fn radio_group(&self) -> Option<&str> {
+        None
     }
+
+    fn threshold_hash_keys(&self) -> Vec<MidiBytes> {
+        self.threshold_hash_keys_inner()
+            .into_iter()
+            .map(Into::into)
+            .collect()
+    }
+    fn exact_hash_keys(&self) -> Vec<MidiBytes> {
+        self.exact_hash_keys_inner()
+            .into_iter()
+            .map(Into::into)
+            .collect()
+    }
+}
+
+/// Turns a trigger into a button with an on/off state of its own. The state is
+/// kept by name, so it survives reloading the config as long as the name
+/// stays the same.
+#[derive(Debug, Deserialize)]
+#[serde(remote = "Self", rename_all = "snake_case", deny_unknown_fields)]
+pub(crate) enum Switch {
+    /// Flips between on and off every time the trigger fires
+    Latch { name: String },
+    /// Turns on when the trigger fires, and turns the other members of
+    /// `group` off
+    Radio { group: String, member: String },
 }
 
-#[derive(Debug)]
+config::tagged_enum!(Switch, "type");
+
+impl Switch {
+    /// Returns the new state
+    fn press(&self, context: &mut Context) -> bool {
+        match self {
+            Self::Latch { name } => context.flip_latch(name),
+            Self::Radio { group, member } => {
+                context.select(group, member);
+                true
+            }
+        }
+    }
+
+    fn on(&self, context: &Context) -> bool {
+        match self {
+            Self::Latch { name } => context.latched(name),
+            Self::Radio { group, member } => context.selected(group) == Some(member),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub(crate) struct TriggerConfig {
+    #[serde(deserialize_with = "trigger::checked")]
     pub(crate) command: TriggerMidiMessage,
-    pub(crate) _auto_indicate: bool,
+    #[serde(default)]
+    pub(crate) action: ActionType,
+    /// Light up the LED of the button sending `command` while the action's
+    /// state is on, or the switch's if there is one
+    #[serde(default)]
+    pub(crate) auto_indicate: bool,
+    /// The action gets the switch's new state instead of a plain trigger
+    #[serde(default)]
+    pub(crate) switch: Option<Switch>,
+    /// Stands in for the state of actions that don't have one, flips every
+    /// time the trigger fires
+    #[serde(skip)]
+    on: Cell<bool>,
+}
+
+impl TriggerConfig {
+    fn show(&self, context: &mut Context) {
+        let on = match &self.switch {
+            Some(switch) => switch.on(context),
+            None => self.action.state(context).unwrap_or(self.on.get()),
+        };
+        for event in self.command.led_events(on) {
+            context.send(&event);
+        }
+    }
 }
 
 impl Control for TriggerConfig {
-    fn handle_midi_event_inner(&self, event: &LiveEvent) {
-        self.command.handle_midi_event_inner(event);
+    fn handle_midi_event(&self, event: &LiveEvent, timestamp: u64, context: &mut Context) {
+        self.command.track(event, timestamp, context);
+        if let Some(by) = self.command.triggered_by(event) {
+            let value = match (&self.switch, by.captured) {
+                (Some(switch), _) => ControlValue::Switch(switch.press(context)),
+                (None, Some(captured)) => ControlValue::Captured(captured),
+                (None, None) => ControlValue::Trigger(by),
+            };
+            self.action.perform(value, context);
+            self.on.set(!self.on.get());
+            if self.auto_indicate {
+                self.show(context);
+            }
+        }
+    }
+    fn indicate(&self, context: &mut Context) {
+        if self.auto_indicate {
+            self.show(context);
+        }
+    }
+    fn captures(&self, event: &LiveEvent, timestamp: u64) -> bool {
+        self.command.captures(event, timestamp)
+    }
+    fn handle_captured_event(&self, event: &LiveEvent, timestamp: u64, context: &mut Context) {
+        self.command.track(event, timestamp, context);
     }
-    fn exact_hash_key_inner(&self) -> Option<LiveEvent> {
-        self.command.exact_hash_key_inner()
+    fn holds_back(&self, event: &LiveEvent) -> Option<Duration> {
+        self.command.holds_back(event)
     }
-    fn threshold_hash_key_inner(&self) -> Option<LiveEvent> {
-        self.command.threshold_hash_key_inner()
+    fn radio_group(&self) -> Option<&str> {
+        match &self.switch {
+            Some(Switch::Radio { group, .. }) => Some(group),
+            _ => None,
+        }
+    }
+    fn exact_hash_keys_inner(&self) -> Vec<LiveEvent<'_>> {
+        self.command.exact_hash_keys_inner()
+    }
+    fn threshold_hash_keys_inner(&self) -> Vec<LiveEvent<'_>> {
+        self.command.threshold_hash_keys_inner()
     }
 }
 
-//struct RelativeValue {
-//    command: MidiMessageMatch,
-//    steps: u16,
-//    up_value: u8,
-//    up_direction: Direction,
-//    down_value: u8,
-//    down_direction: Direction,
-//}
-//
-//struct AbsoluteValue {
-//    command: MidiMessageMatch,
-//    control: u7,
-//    min: u14,
-//    max: u14,
-//}
-//
-//struct Indicator {
-//    command: MidiMessageMatch,
-//    min: u14,
-//    max: u14,
-//}
-
-#[derive(Debug)]
+#[derive(Debug, Deserialize)]
 #[enum_dispatch(Control)]
+#[serde(remote = "Self", rename_all = "snake_case")]
 pub(crate) enum ControlType {
     Trigger(TriggerConfig),
-    //    AbsoluteValue(AbsoluteValue),
-    //    RelativeValue(RelativeValue),
-    //    Indicator(Indicator),
+    AbsoluteValue(AbsoluteValue),
+    Button(Button),
+    RelativeValue(RelativeValue),
+    Indicator(Indicator),
+}
+
+config::tagged_enum!(ControlType, "type");
+
+#[cfg(test)]
+mod tests {
+    use midi_windows_controller::audio::{mock::MockBackend, Audio};
+
+    use super::*;
+
+    #[test]
+    fn auto_indicate() {
+        let trigger: TriggerConfig = toml::from_str(
+            r#"
+            auto_indicate = true
+            command = { message = "note_on", channel = 0, note = 0x59, velocity = 0x7F }
+            "#,
+        )
+        .unwrap();
+        let mut context = Context::new(None, Audio::new(Box::new(MockBackend::default())));
+        trigger.indicate(&mut context);
+        let mut press = |note| {
+            let bytes = [0x90, note, 0x7F];
+            let event = LiveEvent::parse(&bytes).unwrap();
+            trigger.handle_midi_event(&event, 0, &mut context);
+        };
+        press(0x59);
+        // Not ours
+        press(0x5A);
+        press(0x59);
+        let led = |value| MidiBytes::from_slice(&[0x90, 0x59, value]);
+        assert_eq!(context.sent, [led(0), led(0x7F), led(0)]);
+    }
 }
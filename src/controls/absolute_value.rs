@@ -0,0 +1,224 @@
+use midly::{
+    live::LiveEvent,
+    num::{u14, u4, u7},
+    MidiMessage, PitchBend,
+};
+use serde::Deserialize;
+
+use crate::{
+    actions::{Action, ActionType, ControlValue},
+    config,
+    context::Context,
+};
+
+use super::{indicator::Indicator, Control};
+
+/// The MIDI messages that carry an absolute position.
+#[derive(Debug, Deserialize)]
+#[serde(remote = "Self", rename_all = "snake_case", deny_unknown_fields)]
+pub(crate) enum AbsoluteValueSource {
+    Controller {
+        #[serde(deserialize_with = "config::channel")]
+        channel: u4,
+        #[serde(deserialize_with = "config::u7")]
+        controller: u7,
+    },
+    PitchBend {
+        #[serde(deserialize_with = "config::channel")]
+        channel: u4,
+    },
+}
+
+config::tagged_enum!(AbsoluteValueSource, "message");
+
+impl AbsoluteValueSource {
+    /// The raw position carried by `event`, if it is one of ours.
+    fn position(&self, event: &LiveEvent) -> Option<u16> {
+        match (self, event) {
+            (
+                Self::Controller {
+                    channel,
+                    controller,
+                },
+                LiveEvent::Midi {
+                    channel: event_channel,
+                    message:
+                        MidiMessage::Controller {
+                            controller: event_controller,
+                            value,
+                        },
+                },
+            ) if channel == event_channel && controller == event_controller => {
+                Some(value.as_int().into())
+            }
+            (
+                Self::PitchBend { channel },
+                LiveEvent::Midi {
+                    channel: event_channel,
+                    message: MidiMessage::PitchBend { bend },
+                },
+            ) if channel == event_channel => Some(bend.0.as_int()),
+            _ => None,
+        }
+    }
+
+    fn max_position(&self) -> u14 {
+        match self {
+            Self::Controller { .. } => u14::new(u7::max_value().as_int().into()),
+            Self::PitchBend { .. } => u14::max_value(),
+        }
+    }
+}
+
+/// A fader or knob that reports where it is, rather than how far it moved.
+/// Positions between `min` and `max` are mapped onto 0.0 to 1.0, anything
+/// outside of that is clamped.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct AbsoluteValue {
+    pub(crate) command: AbsoluteValueSource,
+    #[serde(default, deserialize_with = "config::u14")]
+    pub(crate) min: u14,
+    /// Defaults to the largest position the message can carry
+    #[serde(default, deserialize_with = "config::optional_u14")]
+    pub(crate) max: Option<u14>,
+    #[serde(default)]
+    pub(crate) action: ActionType,
+    #[serde(default)]
+    pub(crate) invert: bool,
+    /// Mirrors the value back to the controller
+    #[serde(default)]
+    pub(crate) indicator: Option<Indicator>,
+}
+
+impl AbsoluteValue {
+    fn normalize(&self, position: u16) -> f32 {
+        let min = self.min.as_int();
+        let max = self
+            .max
+            .unwrap_or_else(|| self.command.max_position())
+            .as_int();
+        let value = if max > min {
+            f32::from(position.clamp(min, max) - min) / f32::from(max - min)
+        } else if position >= min {
+            // Nothing sensible to scale with, so treat it as a switch
+            1.0
+        } else {
+            0.0
+        };
+        if self.invert {
+            1.0 - value
+        } else {
+            value
+        }
+    }
+}
+
+impl Control for AbsoluteValue {
+    fn handle_midi_event(&self, event: &LiveEvent, _timestamp: u64, context: &mut Context) {
+        if let Some(position) = self.command.position(event) {
+            let value = self.normalize(position);
+            self.action.perform(ControlValue::Absolute(value), context);
+            if let Some(indicator) = &self.indicator {
+                indicator.show(value, context);
+            }
+        }
+    }
+
+    fn threshold_hash_keys_inner(&self) -> Vec<LiveEvent<'_>> {
+        // Every position is of interest, so register under the same
+        // value-less key that `live_event_without_value` produces.
+        vec![match self.command {
+            AbsoluteValueSource::Controller {
+                channel,
+                controller,
+            } => LiveEvent::Midi {
+                channel,
+                message: MidiMessage::Controller {
+                    controller,
+                    value: u7::default(),
+                },
+            },
+            AbsoluteValueSource::PitchBend { channel } => LiveEvent::Midi {
+                channel,
+                message: MidiMessage::PitchBend {
+                    bend: PitchBend::mid_raw_value(),
+                },
+            },
+        }]
+    }
+
+    fn exact_hash_keys_inner(&self) -> Vec<LiveEvent<'_>> {
+        Vec::new()
+    }
+
+    /// Where the fader is isn't known until it moves, so this can only go by
+    /// the action, e.g. to move a motorized fader to the current volume
+    fn indicate(&self, context: &mut Context) {
+        if let Some(indicator) = &self.indicator {
+            if let Some(level) = self.action.level(context) {
+                indicator.show(level, context);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fader(config: &str) -> AbsoluteValue {
+        toml::from_str(config).unwrap()
+    }
+
+    #[test]
+    fn normalize() {
+        let knob = fader("command = { message = \"controller\", channel = 0, controller = 1 }");
+        assert_eq!(knob.normalize(0), 0.0);
+        assert_eq!(knob.normalize(127), 1.0);
+        let pitch_bend = fader("command = { message = \"pitch_bend\", channel = 8 }");
+        assert_eq!(pitch_bend.normalize(0x2000), 8192.0 / 16383.0);
+        assert_eq!(pitch_bend.normalize(0x3FFF), 1.0);
+    }
+
+    #[test]
+    fn normalize_within_bounds() {
+        let fader = fader(
+            "min = 100\nmax = 200\ninvert = true\n\
+             command = { message = \"pitch_bend\", channel = 8 }",
+        );
+        assert_eq!(fader.normalize(0), 1.0);
+        assert_eq!(fader.normalize(150), 0.5);
+        assert_eq!(fader.normalize(0x3FFF), 0.0);
+    }
+
+    #[test]
+    fn normalize_without_a_range() {
+        let switch = fader(
+            "min = 64\nmax = 64\n\
+             command = { message = \"controller\", channel = 0, controller = 1 }",
+        );
+        assert_eq!(switch.normalize(63), 0.0);
+        assert_eq!(switch.normalize(64), 1.0);
+        assert_eq!(switch.normalize(127), 1.0);
+    }
+
+    #[test]
+    fn position() {
+        let fader = fader("command = { message = \"pitch_bend\", channel = 8 }");
+        let event = LiveEvent::Midi {
+            channel: 8.into(),
+            message: MidiMessage::PitchBend {
+                bend: PitchBend(u14::new(0x1234)),
+            },
+        };
+        assert_eq!(fader.command.position(&event), Some(0x1234));
+        let event = LiveEvent::Midi {
+            channel: 7.into(),
+            message: MidiMessage::PitchBend {
+                bend: PitchBend(u14::new(0x1234)),
+            },
+        };
+        assert_eq!(fader.command.position(&event), None);
+    }
+}
@@ -0,0 +1,448 @@
+use std::{
+    cell::Cell,
+    time::{Duration, Instant},
+};
+
+use midly::{
+    live::LiveEvent,
+    num::{u4, u7},
+    MidiMessage,
+};
+use serde::Deserialize;
+
+use crate::{
+    actions::{ActionType, ControlValue, TriggeredBy},
+    config,
+    context::Context,
+};
+
+use super::Control;
+
+/// The MIDI messages a button can be pressed and released with.
+#[derive(Clone, Debug, Deserialize, Eq, Hash, PartialEq)]
+#[serde(remote = "Self", rename_all = "snake_case", deny_unknown_fields)]
+pub(crate) enum ButtonSource {
+    /// Note on to press, note off or note on with velocity 0 to release
+    Note {
+        #[serde(deserialize_with = "config::channel")]
+        channel: u4,
+        #[serde(deserialize_with = "config::u7")]
+        note: u7,
+    },
+    /// Any value above 0 to press, 0 to release
+    Controller {
+        #[serde(deserialize_with = "config::channel")]
+        channel: u4,
+        #[serde(deserialize_with = "config::u7")]
+        controller: u7,
+    },
+}
+
+config::tagged_enum!(ButtonSource, "message");
+
+impl ButtonSource {
+    /// Whether `event` presses (`true`) or releases (`false`) the button, if
+    /// it is one of ours.
+    pub(crate) fn pressed(&self, event: &LiveEvent) -> Option<bool> {
+        let LiveEvent::Midi {
+            channel: event_channel,
+            message,
+        } = event
+        else {
+            return None;
+        };
+        match (self, message) {
+            (Self::Note { channel, note }, MidiMessage::NoteOn { key, vel })
+                if channel == event_channel && note == key =>
+            {
+                Some(*vel > 0)
+            }
+            (Self::Note { channel, note }, MidiMessage::NoteOff { key, .. })
+                if channel == event_channel && note == key =>
+            {
+                Some(false)
+            }
+            (
+                Self::Controller {
+                    channel,
+                    controller,
+                },
+                MidiMessage::Controller {
+                    controller: event_controller,
+                    value,
+                },
+            ) if channel == event_channel && controller == event_controller => Some(*value > 0),
+            _ => None,
+        }
+    }
+
+    /// What lights up the LED of the button, on controllers that take back
+    /// what the button sends
+    pub(crate) fn led_event(&self, on: bool) -> LiveEvent<'static> {
+        let value = if on { u7::max_value() } else { u7::default() };
+        match *self {
+            Self::Note { channel, note } => LiveEvent::Midi {
+                channel,
+                message: MidiMessage::NoteOn {
+                    key: note,
+                    vel: value,
+                },
+            },
+            Self::Controller {
+                channel,
+                controller,
+            } => LiveEvent::Midi {
+                channel,
+                message: MidiMessage::Controller { controller, value },
+            },
+        }
+    }
+
+    /// Every value means something, so these are the same value-less keys
+    /// that `live_event_without_value` produces, to register under as
+    /// threshold keys.
+    pub(crate) fn hash_keys(&self) -> Vec<LiveEvent<'static>> {
+        match *self {
+            Self::Note { channel, note } => vec![
+                LiveEvent::Midi {
+                    channel,
+                    message: MidiMessage::NoteOn {
+                        key: note,
+                        vel: u7::default(),
+                    },
+                },
+                LiveEvent::Midi {
+                    channel,
+                    message: MidiMessage::NoteOff {
+                        key: note,
+                        vel: u7::default(),
+                    },
+                },
+            ],
+            Self::Controller {
+                channel,
+                controller,
+            } => vec![LiveEvent::Midi {
+                channel,
+                message: MidiMessage::Controller {
+                    controller,
+                    value: u7::default(),
+                },
+            }],
+        }
+    }
+
+    fn triggered_by(&self) -> TriggeredBy {
+        let (channel, number) = match self {
+            Self::Note { channel, note } => (channel, note),
+            Self::Controller {
+                channel,
+                controller,
+            } => (channel, controller),
+        };
+        TriggeredBy {
+            channel: Some(*channel),
+            number: Some(*number),
+            captured: None,
+        }
+    }
+}
+
+/// A button with different actions for the different ways of pushing it.
+/// All actions are optional, and `press` and `release` fire on every press
+/// and release regardless of the others. They are boxed, as five actions
+/// inline would make this much bigger than the other controls.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct Button {
+    pub(crate) command: ButtonSource,
+    #[serde(default)]
+    pub(crate) press: Option<Box<ActionType>>,
+    #[serde(default)]
+    pub(crate) release: Option<Box<ActionType>>,
+    /// A short press and release. Waits for `double_tap_ms` to pass first if
+    /// there is a `double_tap` action.
+    #[serde(default)]
+    pub(crate) tap: Option<Box<ActionType>>,
+    /// Held down for `long_press_ms`, fires while the button is still held
+    #[serde(default)]
+    pub(crate) long_press: Option<Box<ActionType>>,
+    /// Pressed again within `double_tap_ms` after a tap
+    #[serde(default)]
+    pub(crate) double_tap: Option<Box<ActionType>>,
+    #[serde(default = "Button::default_long_press_ms")]
+    pub(crate) long_press_ms: u64,
+    #[serde(default = "Button::default_double_tap_ms")]
+    pub(crate) double_tap_ms: u64,
+    #[serde(skip)]
+    pressed_at: Cell<Option<Instant>>,
+    /// Whether the current press is a long press or the second half of a
+    /// double tap, either of which means it isn't a tap
+    #[serde(skip)]
+    press_used: Cell<bool>,
+    /// When the last tap happened that might still become a double tap
+    #[serde(skip)]
+    pending_tap: Cell<Option<Instant>>,
+}
+
+impl Button {
+    fn default_long_press_ms() -> u64 {
+        500
+    }
+
+    fn default_double_tap_ms() -> u64 {
+        300
+    }
+
+    fn perform(&self, action: Option<&ActionType>, context: &mut Context) {
+        if let Some(action) = action {
+            action.perform(ControlValue::Trigger(self.command.triggered_by()), context);
+        }
+    }
+
+    fn long_press_deadline(&self) -> Option<Instant> {
+        self.long_press.as_deref()?;
+        if self.press_used.get() {
+            return None;
+        }
+        Some(self.pressed_at.get()? + Duration::from_millis(self.long_press_ms))
+    }
+
+    fn tap_deadline(&self) -> Option<Instant> {
+        Some(self.pending_tap.get()? + Duration::from_millis(self.double_tap_ms))
+    }
+
+    fn press(&self, now: Instant, context: &mut Context) {
+        self.pressed_at.set(Some(now));
+        self.press_used.set(false);
+        self.perform(self.press.as_deref(), context);
+        if self.pending_tap.take().is_some() {
+            // Still within the window, or `handle_deadline` would have
+            // turned it into a tap already
+            self.press_used.set(true);
+            self.perform(self.double_tap.as_deref(), context);
+        }
+    }
+
+    fn release(&self, now: Instant, context: &mut Context) {
+        if self.pressed_at.take().is_none() {
+            // Held since before the mappings were loaded
+            return;
+        }
+        self.perform(self.release.as_deref(), context);
+        if self.press_used.get() {
+            return;
+        }
+        if self.double_tap.is_some() {
+            self.pending_tap.set(Some(now));
+        } else {
+            self.perform(self.tap.as_deref(), context);
+        }
+    }
+}
+
+impl Control for Button {
+    fn handle_midi_event(&self, event: &LiveEvent, _timestamp: u64, context: &mut Context) {
+        // The timestamps of midi events don't share a clock with deadlines,
+        // so go by when the event got here instead
+        let now = Instant::now();
+        match self.command.pressed(event) {
+            Some(true) => self.press(now, context),
+            Some(false) => self.release(now, context),
+            None => (),
+        }
+    }
+
+    fn handle_captured_event(&self, event: &LiveEvent, _timestamp: u64, _context: &mut Context) {
+        // The press went to a chord or a layer, so it isn't a tap or a long
+        // press, and neither is the one before it a double tap in the making
+        if self.command.pressed(event).is_some() {
+            self.pressed_at.set(None);
+            self.pending_tap.set(None);
+        }
+    }
+
+    fn deadline(&self) -> Option<Instant> {
+        match (self.long_press_deadline(), self.tap_deadline()) {
+            (Some(long_press), Some(tap)) => Some(long_press.min(tap)),
+            (long_press, tap) => long_press.or(tap),
+        }
+    }
+
+    fn handle_deadline(&self, now: Instant, context: &mut Context) {
+        if context.held_by_chord(&self.command) {
+            // Pressed before the rest of a chord was, which took it over
+            self.pressed_at.set(None);
+        }
+        if self
+            .long_press_deadline()
+            .is_some_and(|deadline| deadline <= now)
+        {
+            self.press_used.set(true);
+            self.perform(self.long_press.as_deref(), context);
+        }
+        if self.tap_deadline().is_some_and(|deadline| deadline <= now) {
+            self.pending_tap.set(None);
+            self.perform(self.tap.as_deref(), context);
+        }
+    }
+
+    fn threshold_hash_keys_inner(&self) -> Vec<LiveEvent<'_>> {
+        self.command.hash_keys()
+    }
+
+    fn exact_hash_keys_inner(&self) -> Vec<LiveEvent<'_>> {
+        Vec::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use midi_windows_controller::audio::{mock::MockBackend, Audio, Flow, Target};
+
+    use super::*;
+
+    /// A button on note 0x10 with the given actions, each of which toggles
+    /// the mute of the session named after it
+    fn button(actions: &[&str]) -> Button {
+        let mut config =
+            String::from("command = { message = \"note\", channel = 0, note = 0x10 }\n");
+        for action in actions {
+            config +=
+                &format!("{action} = {{ name = \"session_volume\", process = \"{action}\" }}\n");
+        }
+        toml::from_str(&config).unwrap()
+    }
+
+    fn context() -> Context {
+        let mut audio = MockBackend::default();
+        audio.add_device("speakers", "Speakers", Flow::Render);
+        for (pid, action) in ["tap", "long_press", "double_tap"].into_iter().enumerate() {
+            audio
+                .add_session("speakers", action, Some(action), pid as u32 + 1)
+                .unwrap();
+        }
+        Context::new(None, Audio::new(Box::new(audio)))
+    }
+
+    /// Which actions have run an odd number of times
+    fn fired(context: &Context) -> Vec<&'static str> {
+        ["tap", "long_press", "double_tap"]
+            .into_iter()
+            .filter(|action| {
+                context
+                    .audio
+                    .backend()
+                    .muted(&Target::Session(action.to_string()))
+                    .unwrap()
+            })
+            .collect()
+    }
+
+    fn ms(start: Instant, ms: u64) -> Instant {
+        start + Duration::from_millis(ms)
+    }
+
+    #[test]
+    fn note_and_controller_sources() {
+        let note = button(&[]);
+        assert_eq!(
+            note.command
+                .pressed(&LiveEvent::parse(&[0x90, 0x10, 0x7F]).unwrap()),
+            Some(true)
+        );
+        assert_eq!(
+            note.command
+                .pressed(&LiveEvent::parse(&[0x90, 0x10, 0x00]).unwrap()),
+            Some(false)
+        );
+        assert_eq!(
+            note.command
+                .pressed(&LiveEvent::parse(&[0x80, 0x10, 0x40]).unwrap()),
+            Some(false)
+        );
+        assert_eq!(
+            note.command
+                .pressed(&LiveEvent::parse(&[0x90, 0x11, 0x7F]).unwrap()),
+            None
+        );
+        let knob: Button = toml::from_str(
+            "command = { message = \"controller\", channel = 1, controller = 0x40 }",
+        )
+        .unwrap();
+        assert_eq!(
+            knob.command
+                .pressed(&LiveEvent::parse(&[0xB1, 0x40, 0x01]).unwrap()),
+            Some(true)
+        );
+        assert_eq!(
+            knob.command
+                .pressed(&LiveEvent::parse(&[0xB1, 0x40, 0x00]).unwrap()),
+            Some(false)
+        );
+        let error = toml::from_str::<Button>("command = { message = \"pitch_bend\", channel = 0 }")
+            .unwrap_err();
+        assert!(error.message().contains("unknown variant"), "{error}");
+    }
+
+    #[test]
+    fn tap_fires_on_release() {
+        let button = button(&["tap", "long_press"]);
+        let mut context = context();
+        let start = Instant::now();
+        button.press(start, &mut context);
+        assert_eq!(button.deadline(), Some(ms(start, 500)));
+        button.release(ms(start, 100), &mut context);
+        assert_eq!(fired(&context), ["tap"]);
+        // Nothing left to wait for
+        assert_eq!(button.deadline(), None);
+    }
+
+    #[test]
+    fn long_press_fires_while_held() {
+        let button = button(&["tap", "long_press"]);
+        let mut context = context();
+        let start = Instant::now();
+        button.press(start, &mut context);
+        button.handle_deadline(ms(start, 499), &mut context);
+        assert!(fired(&context).is_empty());
+        button.handle_deadline(ms(start, 500), &mut context);
+        assert_eq!(fired(&context), ["long_press"]);
+        assert_eq!(button.deadline(), None);
+        // Not a tap as well
+        button.release(ms(start, 600), &mut context);
+        assert_eq!(fired(&context), ["long_press"]);
+    }
+
+    #[test]
+    fn double_tap_holds_back_the_tap() {
+        let button = button(&["tap", "double_tap"]);
+        let mut context = context();
+        let start = Instant::now();
+        button.press(start, &mut context);
+        button.release(ms(start, 50), &mut context);
+        assert!(fired(&context).is_empty());
+        assert_eq!(button.deadline(), Some(ms(start, 350)));
+        button.press(ms(start, 200), &mut context);
+        assert_eq!(fired(&context), ["double_tap"]);
+        button.release(ms(start, 250), &mut context);
+        button.handle_deadline(ms(start, 1000), &mut context);
+        assert_eq!(fired(&context), ["double_tap"]);
+
+        // Too slow for a double tap, so it's a tap after all
+        button.press(ms(start, 2000), &mut context);
+        button.release(ms(start, 2050), &mut context);
+        button.handle_deadline(ms(start, 2349), &mut context);
+        assert_eq!(fired(&context), ["double_tap"]);
+        button.handle_deadline(ms(start, 2350), &mut context);
+        assert_eq!(fired(&context), ["tap", "double_tap"]);
+    }
+
+    #[test]
+    fn release_without_press_is_ignored() {
+        let button = button(&["tap"]);
+        let mut context = context();
+        button.release(Instant::now(), &mut context);
+        assert!(fired(&context).is_empty());
+    }
+}
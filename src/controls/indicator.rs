@@ -0,0 +1,226 @@
+use midly::{
+    live::LiveEvent,
+    num::{u14, u4, u7},
+    MidiMessage, PitchBend,
+};
+use serde::Deserialize;
+
+use crate::{
+    actions::{Action, ActionType},
+    config,
+    context::Context,
+};
+
+use super::Control;
+
+/// The MIDI messages an indicator can be rendered as.
+#[derive(Debug, Deserialize)]
+#[serde(remote = "Self", rename_all = "snake_case", deny_unknown_fields)]
+pub(crate) enum IndicatorTarget {
+    /// Velocity is the value, usually for the LED of a button
+    NoteOn {
+        #[serde(deserialize_with = "config::channel")]
+        channel: u4,
+        #[serde(deserialize_with = "config::u7")]
+        note: u7,
+    },
+    /// Usually for LED rings around knobs
+    Controller {
+        #[serde(deserialize_with = "config::channel")]
+        channel: u4,
+        #[serde(deserialize_with = "config::u7")]
+        controller: u7,
+    },
+    /// Usually for motorized faders
+    PitchBend {
+        #[serde(deserialize_with = "config::channel")]
+        channel: u4,
+    },
+}
+
+config::tagged_enum!(IndicatorTarget, "message");
+
+impl IndicatorTarget {
+    fn max_value(&self) -> u14 {
+        match self {
+            Self::NoteOn { .. } | Self::Controller { .. } => {
+                u14::new(u7::max_value().as_int().into())
+            }
+            Self::PitchBend { .. } => u14::max_value(),
+        }
+    }
+}
+
+/// Shows a value from 0.0 to 1.0 on the controller, by sending values between
+/// `min` and `max` back to it.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct Indicator {
+    pub(crate) command: IndicatorTarget,
+    #[serde(default, deserialize_with = "config::u14")]
+    pub(crate) min: u14,
+    /// Defaults to the largest value the message can carry
+    #[serde(default, deserialize_with = "config::optional_u14")]
+    pub(crate) max: Option<u14>,
+    /// For an indicator of its own, shows the level of this action, e.g. a
+    /// volume
+    #[serde(default)]
+    pub(crate) action: Option<ActionType>,
+    /// Shown when the mappings are loaded, if there is no `action` or it
+    /// has no level
+    #[serde(default)]
+    pub(crate) value: Option<f32>,
+}
+
+impl Indicator {
+    pub(crate) fn render(&self, value: f32) -> LiveEvent<'static> {
+        let max_value = self.command.max_value().as_int();
+        let min = self.min.as_int().min(max_value);
+        let max = self.max.map_or(max_value, u14::as_int).min(max_value);
+        #[allow(
+            clippy::cast_possible_truncation,
+            clippy::cast_sign_loss,
+            clippy::cast_precision_loss
+        )]
+        let raw = (f32::from(min) + value.clamp(0.0, 1.0) * (f32::from(max) - f32::from(min)))
+            .round() as u16;
+        // Only used for the 7-bit messages, where `raw` can't be over 127
+        #[allow(clippy::cast_possible_truncation)]
+        let seven_bit = u7::new(raw as u8);
+        match self.command {
+            IndicatorTarget::NoteOn { channel, note } => LiveEvent::Midi {
+                channel,
+                message: MidiMessage::NoteOn {
+                    key: note,
+                    vel: seven_bit,
+                },
+            },
+            IndicatorTarget::Controller {
+                channel,
+                controller,
+            } => LiveEvent::Midi {
+                channel,
+                message: MidiMessage::Controller {
+                    controller,
+                    value: seven_bit,
+                },
+            },
+            IndicatorTarget::PitchBend { channel } => LiveEvent::Midi {
+                channel,
+                message: MidiMessage::PitchBend {
+                    bend: PitchBend(u14::new(raw)),
+                },
+            },
+        }
+    }
+
+    pub(crate) fn show(&self, value: f32, context: &mut Context) {
+        context.send(&self.render(value));
+    }
+}
+
+impl Control for Indicator {
+    fn handle_midi_event(&self, _event: &LiveEvent, _timestamp: u64, _context: &mut Context) {}
+
+    fn threshold_hash_keys_inner(&self) -> Vec<LiveEvent<'_>> {
+        Vec::new()
+    }
+
+    fn exact_hash_keys_inner(&self) -> Vec<LiveEvent<'_>> {
+        Vec::new()
+    }
+
+    fn indicate(&self, context: &mut Context) {
+        let level = self
+            .action
+            .as_ref()
+            .and_then(|action| action.level(context));
+        if let Some(value) = level.or(self.value) {
+            self.show(value, context);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use midi_windows_controller::audio::{mock::MockBackend, Audio};
+
+    use super::*;
+    use crate::MidiBytes;
+
+    fn indicator(config: &str) -> Indicator {
+        toml::from_str(config).unwrap()
+    }
+
+    fn controller(value: u8) -> LiveEvent<'static> {
+        LiveEvent::Midi {
+            channel: 0.into(),
+            message: MidiMessage::Controller {
+                controller: 0x30.into(),
+                value: value.into(),
+            },
+        }
+    }
+
+    #[test]
+    fn render_within_bounds() {
+        let ring = indicator(
+            "min = 1\nmax = 11\n\
+             command = { message = \"controller\", channel = 0, controller = 0x30 }",
+        );
+        assert_eq!(ring.render(0.0), controller(1));
+        assert_eq!(ring.render(0.5), controller(6));
+        assert_eq!(ring.render(1.0), controller(11));
+        // Out of range values are clamped
+        assert_eq!(ring.render(-1.0), controller(1));
+        assert_eq!(ring.render(2.0), controller(11));
+    }
+
+    #[test]
+    fn render_full_range() {
+        let led = indicator("command = { message = \"note_on\", channel = 1, note = 0x59 }");
+        assert_eq!(
+            led.render(1.0),
+            LiveEvent::Midi {
+                channel: 1.into(),
+                message: MidiMessage::NoteOn {
+                    key: 0x59.into(),
+                    vel: 0x7F.into(),
+                },
+            }
+        );
+        let fader = indicator("command = { message = \"pitch_bend\", channel = 8 }");
+        assert_eq!(
+            fader.render(1.0),
+            LiveEvent::Midi {
+                channel: 8.into(),
+                message: MidiMessage::PitchBend {
+                    bend: PitchBend(u14::max_value()),
+                },
+            }
+        );
+    }
+
+    #[test]
+    fn max_beyond_the_message() {
+        let ring = indicator(
+            "max = 1000\n\
+             command = { message = \"controller\", channel = 0, controller = 0x30 }",
+        );
+        assert_eq!(ring.render(1.0), controller(0x7F));
+    }
+
+    #[test]
+    fn indicate_value() {
+        let mut context = Context::new(None, Audio::new(Box::new(MockBackend::default())));
+        let led = indicator("command = { message = \"note_on\", channel = 0, note = 0x59 }");
+        led.indicate(&mut context);
+        assert!(context.sent.is_empty());
+        let led = indicator(
+            "value = 1.0\n\
+             command = { message = \"note_on\", channel = 0, note = 0x59 }",
+        );
+        led.indicate(&mut context);
+        assert_eq!(context.sent, [MidiBytes::from_slice(&[0x90, 0x59, 0x7F])]);
+    }
+}
@@ -0,0 +1,442 @@
+use std::cell::Cell;
+
+use midly::{
+    live::LiveEvent,
+    num::{u4, u7},
+    MidiMessage,
+};
+use serde::Deserialize;
+
+use crate::{
+    actions::{Action, ActionType, ControlValue},
+    config,
+    context::Context,
+};
+
+use super::{indicator::Indicator, Control};
+
+/// The MIDI messages that carry a relative movement.
+#[derive(Debug, Deserialize)]
+#[serde(remote = "Self", rename_all = "snake_case", deny_unknown_fields)]
+pub(crate) enum RelativeValueSource {
+    Controller {
+        #[serde(deserialize_with = "config::channel")]
+        channel: u4,
+        #[serde(deserialize_with = "config::u7")]
+        controller: u7,
+    },
+}
+
+config::tagged_enum!(RelativeValueSource, "message");
+
+impl RelativeValueSource {
+    /// The raw value carried by `event`, if it is one of ours.
+    fn value(&self, event: &LiveEvent) -> Option<u7> {
+        match (self, event) {
+            (
+                Self::Controller {
+                    channel,
+                    controller,
+                },
+                LiveEvent::Midi {
+                    channel: event_channel,
+                    message:
+                        MidiMessage::Controller {
+                            controller: event_controller,
+                            value,
+                        },
+                },
+            ) if channel == event_channel && controller == event_controller => Some(*value),
+            _ => None,
+        }
+    }
+}
+
+/// How an endless encoder packs the direction and size of a movement into a
+/// single 7-bit value. Controllers differ wildly here, and some can be
+/// switched between several of these.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+pub(crate) enum RelativeEncoding {
+    /// 1 to 63 is up, 127 (-1) down to 64 (-64) is down
+    TwosComplement,
+    /// Bit 6 is the sign, set for down, the lower 6 bits are the magnitude
+    SignMagnitude,
+    /// 64 is no movement, 65 and up is up, 63 and down is down
+    BinaryOffset,
+    /// One fixed value for a step up and another for a step down
+    IncrementDecrement {
+        #[serde(deserialize_with = "config::u7")]
+        up_value: u7,
+        #[serde(deserialize_with = "config::u7")]
+        down_value: u7,
+    },
+}
+
+impl RelativeEncoding {
+    fn decode(&self, value: u7) -> i32 {
+        let raw = i32::from(value.as_int());
+        match self {
+            Self::TwosComplement => {
+                if raw < 64 {
+                    raw
+                } else {
+                    raw - 128
+                }
+            }
+            Self::SignMagnitude => {
+                if raw & 0x40 == 0 {
+                    raw
+                } else {
+                    -(raw & 0x3F)
+                }
+            }
+            Self::BinaryOffset => raw - 64,
+            Self::IncrementDecrement {
+                up_value,
+                down_value,
+            } => {
+                if value == *up_value {
+                    1
+                } else if value == *down_value {
+                    -1
+                } else {
+                    0
+                }
+            }
+        }
+    }
+}
+
+/// Scales steps up when the encoder is turned quickly, so that sweeping a
+/// whole range doesn't take dozens of turns while single detents stay
+/// precise.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct Acceleration {
+    /// Messages this many milliseconds or more apart aren't accelerated
+    #[serde(default = "Acceleration::default_slow_ms")]
+    pub(crate) slow_ms: u32,
+    /// Messages this many milliseconds or less apart get `max_multiplier`
+    #[serde(default = "Acceleration::default_fast_ms")]
+    pub(crate) fast_ms: u32,
+    #[serde(default = "Acceleration::default_max_multiplier")]
+    pub(crate) max_multiplier: f32,
+    /// Shape of the ramp between `slow_ms` and `fast_ms`, 1.0 is linear and
+    /// higher values keep moderate speeds closer to 1x
+    #[serde(default = "Acceleration::default_curve")]
+    pub(crate) curve: f32,
+}
+
+impl Acceleration {
+    fn default_slow_ms() -> u32 {
+        100
+    }
+
+    fn default_fast_ms() -> u32 {
+        10
+    }
+
+    fn default_max_multiplier() -> f32 {
+        8.0
+    }
+
+    fn default_curve() -> f32 {
+        2.0
+    }
+
+    /// `gap` is the time since the previous message in microseconds
+    #[allow(clippy::cast_precision_loss)]
+    fn multiplier(&self, gap: u64) -> f32 {
+        let gap_ms = gap as f32 / 1000.0;
+        let slow_ms = self.slow_ms as f32;
+        let fast_ms = self.fast_ms as f32;
+        let speed = if slow_ms <= fast_ms {
+            if gap_ms <= fast_ms {
+                1.0
+            } else {
+                0.0
+            }
+        } else {
+            ((slow_ms - gap_ms) / (slow_ms - fast_ms)).clamp(0.0, 1.0)
+        };
+        1.0 + (self.max_multiplier - 1.0).max(0.0) * speed.powf(self.curve)
+    }
+}
+
+/// An endless encoder, which reports how many steps it was turned by rather
+/// than where it is.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct RelativeValue {
+    pub(crate) command: RelativeValueSource,
+    pub(crate) encoding: RelativeEncoding,
+    #[serde(default)]
+    pub(crate) action: ActionType,
+    #[serde(default)]
+    pub(crate) invert: bool,
+    #[serde(default)]
+    pub(crate) acceleration: Option<Acceleration>,
+    /// Shows the position on the controller, typically on an LED ring
+    #[serde(default)]
+    pub(crate) indicator: Option<Indicator>,
+    /// How many steps it takes to go from one end of the range to the other.
+    /// Only used to keep track of the position for `indicator`.
+    #[serde(default = "RelativeValue::default_steps")]
+    pub(crate) steps: u16,
+    /// Timestamp and direction of the previous movement
+    #[serde(skip)]
+    previous: Cell<Option<(u64, i32)>>,
+    /// From 0.0 to 1.0. Follows the level of the action where it has one,
+    /// otherwise it is only counted up and down from 0.0.
+    #[serde(skip)]
+    position: Cell<f32>,
+}
+
+impl RelativeValue {
+    fn default_steps() -> u16 {
+        100
+    }
+
+    /// The signed number of steps the encoder was turned by, 0 if `event`
+    /// isn't ours or didn't move anything.
+    fn steps(&self, event: &LiveEvent, timestamp: u64) -> i32 {
+        let Some(value) = self.command.value(event) else {
+            return 0;
+        };
+        let steps = self.encoding.decode(value);
+        let steps = if self.invert { -steps } else { steps };
+        if steps == 0 {
+            return 0;
+        }
+        let previous = self.previous.replace(Some((timestamp, steps.signum())));
+        let Some(acceleration) = &self.acceleration else {
+            return steps;
+        };
+        // Only accelerate when turning the same way as before, so that
+        // backing up after overshooting is always precise.
+        let multiplier = match previous {
+            Some((previous_timestamp, direction)) if direction == steps.signum() => {
+                acceleration.multiplier(timestamp.saturating_sub(previous_timestamp))
+            }
+            _ => 1.0,
+        };
+        #[allow(clippy::cast_possible_truncation, clippy::cast_precision_loss)]
+        let accelerated = (steps as f32 * multiplier).round() as i32;
+        accelerated
+    }
+}
+
+impl Control for RelativeValue {
+    fn handle_midi_event(&self, event: &LiveEvent, timestamp: u64, context: &mut Context) {
+        let steps = self.steps(event, timestamp);
+        if steps == 0 {
+            return;
+        }
+        self.action.perform(ControlValue::Relative(steps), context);
+        if let Some(indicator) = &self.indicator {
+            // Go by where the action ended up if it can tell, as it may have
+            // hit a limit of its own, or moved by something else since
+            let position = self.action.level(context).unwrap_or_else(|| {
+                #[allow(clippy::cast_precision_loss)]
+                let delta = steps as f32 / f32::from(self.steps.max(1));
+                (self.position.get() + delta).clamp(0.0, 1.0)
+            });
+            self.position.set(position);
+            indicator.show(position, context);
+        }
+    }
+
+    fn threshold_hash_keys_inner(&self) -> Vec<LiveEvent<'_>> {
+        // Every value means something, so register under the same value-less
+        // key that `live_event_without_value` produces.
+        vec![match self.command {
+            RelativeValueSource::Controller {
+                channel,
+                controller,
+            } => LiveEvent::Midi {
+                channel,
+                message: MidiMessage::Controller {
+                    controller,
+                    value: u7::default(),
+                },
+            },
+        }]
+    }
+
+    fn exact_hash_keys_inner(&self) -> Vec<LiveEvent<'_>> {
+        Vec::new()
+    }
+
+    fn indicate(&self, context: &mut Context) {
+        if let Some(indicator) = &self.indicator {
+            if let Some(level) = self.action.level(context) {
+                self.position.set(level);
+            }
+            indicator.show(self.position.get(), context);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use midi_windows_controller::audio::{mock::MockBackend, Audio};
+
+    use super::*;
+    use crate::MidiBytes;
+
+    fn decode(encoding: &RelativeEncoding, values: &[(u8, i32)]) {
+        for &(value, expected) in values {
+            assert_eq!(
+                encoding.decode(u7::new(value)),
+                expected,
+                "{encoding:?} decoding {value:#04X}"
+            );
+        }
+    }
+
+    #[test]
+    fn twos_complement() {
+        decode(
+            &RelativeEncoding::TwosComplement,
+            &[
+                (0x00, 0),
+                (0x01, 1),
+                (0x3F, 63),
+                (0x40, -64),
+                (0x41, -63),
+                (0x7F, -1),
+            ],
+        );
+    }
+
+    #[test]
+    fn sign_magnitude() {
+        decode(
+            &RelativeEncoding::SignMagnitude,
+            &[
+                (0x00, 0),
+                (0x01, 1),
+                (0x3F, 63),
+                (0x40, 0),
+                (0x41, -1),
+                (0x7F, -63),
+            ],
+        );
+    }
+
+    #[test]
+    fn binary_offset() {
+        decode(
+            &RelativeEncoding::BinaryOffset,
+            &[
+                (0x00, -64),
+                (0x01, -63),
+                (0x3F, -1),
+                (0x40, 0),
+                (0x41, 1),
+                (0x7F, 63),
+            ],
+        );
+    }
+
+    #[test]
+    fn increment_decrement() {
+        decode(
+            &RelativeEncoding::IncrementDecrement {
+                up_value: u7::new(0x41),
+                down_value: u7::new(0x3F),
+            },
+            &[
+                (0x00, 0),
+                (0x01, 0),
+                (0x3F, -1),
+                (0x40, 0),
+                (0x41, 1),
+                (0x7F, 0),
+            ],
+        );
+    }
+
+    fn acceleration(slow_ms: u32, fast_ms: u32, max_multiplier: f32) -> Acceleration {
+        Acceleration {
+            slow_ms,
+            fast_ms,
+            max_multiplier,
+            curve: Acceleration::default_curve(),
+        }
+    }
+
+    fn assert_multiplier(acceleration: &Acceleration, gap_ms: u64, expected: f32) {
+        let multiplier = acceleration.multiplier(gap_ms * 1000);
+        assert!(
+            (multiplier - expected).abs() < 1e-4,
+            "{multiplier} instead of {expected} after {gap_ms} ms"
+        );
+    }
+
+    #[test]
+    fn acceleration_curve() {
+        let acceleration = acceleration(100, 10, 8.0);
+        // Slower than `slow_ms` is never accelerated
+        assert_multiplier(&acceleration, 1000, 1.0);
+        assert_multiplier(&acceleration, 100, 1.0);
+        // Halfway between the two is a quarter of the way up with a curve of 2
+        assert_multiplier(&acceleration, 55, 1.0 + 7.0 * 0.25);
+        // Faster than `fast_ms` is capped at the maximum
+        assert_multiplier(&acceleration, 10, 8.0);
+        assert_multiplier(&acceleration, 0, 8.0);
+    }
+
+    #[test]
+    fn acceleration_without_ramp() {
+        // No room for a ramp, so it's either the maximum or nothing
+        let acceleration = acceleration(10, 10, 4.0);
+        assert_multiplier(&acceleration, 10, 4.0);
+        assert_multiplier(&acceleration, 11, 1.0);
+    }
+
+    #[test]
+    fn acceleration_never_slows_down() {
+        let acceleration = acceleration(100, 10, 0.5);
+        assert_multiplier(&acceleration, 0, 1.0);
+        assert_multiplier(&acceleration, 1000, 1.0);
+    }
+
+    fn turn(encoder: &RelativeValue, value: u8, context: &mut Context) {
+        let event = LiveEvent::Midi {
+            channel: 0.into(),
+            message: MidiMessage::Controller {
+                controller: 0x10.into(),
+                value: value.into(),
+            },
+        };
+        encoder.handle_midi_event(&event, 0, context);
+    }
+
+    #[test]
+    fn indicator_follows_the_position() {
+        let encoder: RelativeValue = toml::from_str(
+            r#"
+            command = { message = "controller", channel = 0, controller = 0x10 }
+            encoding = "twos_complement"
+            steps = 10
+            indicator = { command = { message = "controller", channel = 0, controller = 0x30 } }
+            "#,
+        )
+        .unwrap();
+        let mut context = Context::new(None, Audio::new(Box::new(MockBackend::default())));
+        encoder.indicate(&mut context);
+        turn(&encoder, 0x01, &mut context);
+        turn(&encoder, 0x01, &mut context);
+        turn(&encoder, 0x7F, &mut context);
+        // Can't go below the bottom
+        turn(&encoder, 0x40, &mut context);
+        encoder.indicate(&mut context);
+        let ring = |value| MidiBytes::from_slice(&[0xB0, 0x30, value]);
+        assert_eq!(
+            context.sent,
+            [ring(0), ring(13), ring(25), ring(13), ring(0), ring(0)]
+        );
+    }
+}
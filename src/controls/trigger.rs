@@ -1,3 +1,9 @@
+use std::{
+    cell::{Cell, RefCell},
+    str::FromStr,
+    time::Duration,
+};
+
 use enum_dispatch::enum_dispatch;
 use midly::{
     live::{LiveEvent, MtcQuarterFrameMessage, SystemCommon, SystemRealtime},
@@ -5,765 +11,1137 @@ use midly::{
     MidiMessage, PitchBend,
 };
 
-use crate::MidiBytes;
+use serde::{de, Deserialize, Deserializer};
 
-use super::Control;
+use crate::{
+    actions::{Captured, TriggeredBy},
+    config,
+    context::Context,
+    MidiBytes,
+};
 
-#[derive(Debug, Eq, PartialEq)]
+use super::button::ButtonSource;
+
+/// How the value of a message is compared to the one in the config. The range
+/// types take the configured value as the low end, and `high` as the high
+/// end, both inclusive.
+#[derive(Debug, Default, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
 pub(crate) enum ValueMatchType {
+    #[default]
     Exact,
     ThresholdOrAbove,
     ThresholdOrBelow,
+    Range,
+    OutsideRange,
+}
+
+impl ValueMatchType {
+    fn matches<T: PartialOrd + Copy>(&self, value: T, configured: T, high: Option<T>) -> bool {
+        match self {
+            ValueMatchType::Exact => value == configured,
+            ValueMatchType::ThresholdOrAbove => value >= configured,
+            ValueMatchType::ThresholdOrBelow => value <= configured,
+            // `check` makes sure there is a `high` for these
+            ValueMatchType::Range => high.is_some_and(|high| (configured..=high).contains(&value)),
+            ValueMatchType::OutsideRange => {
+                high.is_some_and(|high| !(configured..=high).contains(&value))
+            }
+        }
+    }
+
+    fn check<T: PartialOrd + Copy>(&self, low: T, high: Option<T>) -> Result<(), String> {
+        match (self, high) {
+            (ValueMatchType::Range | ValueMatchType::OutsideRange, None) => {
+                Err("the range match types need a `high` as well".to_string())
+            }
+            (ValueMatchType::Range | ValueMatchType::OutsideRange, Some(high)) if high < low => {
+                Err("`high` is below the low end of the range".to_string())
+            }
+            (
+                ValueMatchType::Exact
+                | ValueMatchType::ThresholdOrAbove
+                | ValueMatchType::ThresholdOrBelow,
+                Some(_),
+            ) => Err("`high` only goes with the range match types".to_string()),
+            _ => Ok(()),
+        }
+    }
 }
 
 #[enum_dispatch]
 pub(crate) trait Trigger {
-    fn is_triggered_by(&self, event: &LiveEvent) -> bool;
+    /// Where `event` came from, if it is one that fires this trigger
+    fn triggered_by(&self, event: &LiveEvent) -> Option<TriggeredBy>;
+    fn threshold_hash_keys_inner(&self) -> Vec<LiveEvent<'_>>;
+    fn exact_hash_keys_inner(&self) -> Vec<LiveEvent<'_>>;
+
+    /// Catches combinations of settings that can't work, which serde can't
+    /// see by looking at one field at a time
+    fn check(&self) -> Result<(), String> {
+        Ok(())
+    }
+
+    /// Keeps up with what `event` changes, for triggers that fire on a
+    /// combination of messages rather than on one. Called before
+    /// `triggered_by`, and for messages that another control captured.
+    fn track(&self, _event: &LiveEvent, _timestamp: u64, _context: &mut Context) {}
+
+    /// See `Control::captures`
+    fn captures(&self, _event: &LiveEvent, _timestamp: u64) -> bool {
+        false
+    }
+
+    /// See `Control::holds_back`
+    fn holds_back(&self, _event: &LiveEvent) -> Option<Duration> {
+        None
+    }
+}
+
+/// One event for every combination of the channels and notes/controllers a
+/// trigger covers, to register it under.
+fn for_each_number<F>(channels: &[u4], numbers: &[u7], event: F) -> Vec<LiveEvent<'static>>
+where
+    F: Fn(u4, u7) -> LiveEvent<'static>,
+{
+    channels
+        .iter()
+        .flat_map(|channel| numbers.iter().map(|number| event(*channel, *number)))
+        .collect()
 }
 
-#[derive(Debug)]
+fn for_each_channel<F>(channels: &[u4], event: F) -> Vec<LiveEvent<'static>>
+where
+    F: Fn(u4) -> LiveEvent<'static>,
+{
+    channels.iter().map(|channel| event(*channel)).collect()
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub(crate) struct TriggerNoteOn {
-    pub(crate) channel: u4,
-    pub(crate) note: u7,
+    #[serde(deserialize_with = "config::channels")]
+    pub(crate) channel: Vec<u4>,
+    #[serde(deserialize_with = "config::u7s")]
+    pub(crate) note: Vec<u7>,
+    #[serde(deserialize_with = "config::u7")]
     pub(crate) velocity: u7,
+    #[serde(default)]
     pub(crate) match_type: ValueMatchType,
+    #[serde(default, deserialize_with = "config::optional_u7")]
+    pub(crate) high: Option<u7>,
+}
+
+impl TriggerNoteOn {
+    fn events(&self, vel: u7) -> Vec<LiveEvent<'static>> {
+        for_each_number(&self.channel, &self.note, |channel, key| LiveEvent::Midi {
+            channel,
+            message: MidiMessage::NoteOn { key, vel },
+        })
+    }
 }
 
 impl Trigger for TriggerNoteOn {
-    fn is_triggered_by(&self, event: &LiveEvent) -> bool {
+    fn triggered_by(&self, event: &LiveEvent) -> Option<TriggeredBy> {
         if let LiveEvent::Midi {
             channel,
             message: MidiMessage::NoteOn { key, vel },
         } = event
         {
-            if self.channel == *channel && self.note == *key {
-                return match self.match_type {
-                    ValueMatchType::Exact => *vel == self.velocity,
-                    ValueMatchType::ThresholdOrAbove => *vel >= self.velocity,
-                    ValueMatchType::ThresholdOrBelow => *vel <= self.velocity,
-                };
+            if self.channel.contains(channel)
+                && self.note.contains(key)
+                && self.match_type.matches(*vel, self.velocity, self.high)
+            {
+                return Some(TriggeredBy {
+                    channel: Some(*channel),
+                    number: Some(*key),
+                    captured: None,
+                });
             }
         }
-        false
+        None
     }
-}
 
-impl Control for TriggerNoteOn {
-    fn handle_midi_event_inner(&self, event: &LiveEvent) {
-        if self.is_triggered_by(event) {
-            println!("TriggerNoteOn: {event:?}");
-        }
+    fn check(&self) -> Result<(), String> {
+        self.match_type.check(self.velocity, self.high)
     }
 
-    fn threshold_hash_key_inner(&self) -> Option<LiveEvent> {
+    fn threshold_hash_keys_inner(&self) -> Vec<LiveEvent<'_>> {
         match self.match_type {
-            ValueMatchType::ThresholdOrAbove | ValueMatchType::ThresholdOrBelow => {
-                Some(LiveEvent::Midi {
-                    channel: self.channel,
-                    message: MidiMessage::NoteOn {
-                        key: self.note,
-                        vel: u7::default(),
-                    },
-                })
-            }
-            ValueMatchType::Exact => None,
+            ValueMatchType::ThresholdOrAbove
+            | ValueMatchType::ThresholdOrBelow
+            | ValueMatchType::Range
+            | ValueMatchType::OutsideRange => self.events(u7::default()),
+            ValueMatchType::Exact => Vec::new(),
         }
     }
 
-    fn exact_hash_key_inner(&self) -> Option<LiveEvent> {
+    fn exact_hash_keys_inner(&self) -> Vec<LiveEvent<'_>> {
         if self.match_type == ValueMatchType::Exact {
-            return Some(LiveEvent::Midi {
-                channel: self.channel,
-                message: MidiMessage::NoteOn {
-                    key: self.note,
-                    vel: self.velocity,
-                },
-            });
+            return self.events(self.velocity);
         }
-        None
+        Vec::new()
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub(crate) struct TriggerNoteOff {
-    pub(crate) channel: u4,
-    pub(crate) note: u7,
+    #[serde(deserialize_with = "config::channels")]
+    pub(crate) channel: Vec<u4>,
+    #[serde(deserialize_with = "config::u7s")]
+    pub(crate) note: Vec<u7>,
+    #[serde(deserialize_with = "config::u7")]
     pub(crate) velocity: u7,
+    #[serde(default)]
     pub(crate) match_type: ValueMatchType,
+    #[serde(default, deserialize_with = "config::optional_u7")]
+    pub(crate) high: Option<u7>,
+}
+
+impl TriggerNoteOff {
+    fn events(&self, vel: u7) -> Vec<LiveEvent<'static>> {
+        for_each_number(&self.channel, &self.note, |channel, key| LiveEvent::Midi {
+            channel,
+            message: MidiMessage::NoteOff { key, vel },
+        })
+    }
 }
 
 impl Trigger for TriggerNoteOff {
-    fn is_triggered_by(&self, event: &LiveEvent) -> bool {
+    fn triggered_by(&self, event: &LiveEvent) -> Option<TriggeredBy> {
         if let LiveEvent::Midi {
             channel,
             message: MidiMessage::NoteOff { key, vel },
         } = event
         {
-            if self.channel == *channel && self.note == *key {
-                return match self.match_type {
-                    ValueMatchType::Exact => *vel == self.velocity,
-                    ValueMatchType::ThresholdOrAbove => *vel >= self.velocity,
-                    ValueMatchType::ThresholdOrBelow => *vel <= self.velocity,
-                };
+            if self.channel.contains(channel)
+                && self.note.contains(key)
+                && self.match_type.matches(*vel, self.velocity, self.high)
+            {
+                return Some(TriggeredBy {
+                    channel: Some(*channel),
+                    number: Some(*key),
+                    captured: None,
+                });
             }
         }
-        false
+        None
     }
-}
 
-impl Control for TriggerNoteOff {
-    fn handle_midi_event_inner(&self, event: &LiveEvent) {
-        if self.is_triggered_by(event) {
-            println!("TriggerNoteOff: {event:?}");
-        }
+    fn check(&self) -> Result<(), String> {
+        self.match_type.check(self.velocity, self.high)
     }
 
-    fn threshold_hash_key_inner(&self) -> Option<LiveEvent> {
+    fn threshold_hash_keys_inner(&self) -> Vec<LiveEvent<'_>> {
         match self.match_type {
-            ValueMatchType::ThresholdOrAbove | ValueMatchType::ThresholdOrBelow => {
-                Some(LiveEvent::Midi {
-                    channel: self.channel,
-                    message: MidiMessage::NoteOff {
-                        key: self.note,
-                        vel: u7::default(),
-                    },
-                })
-            }
-            ValueMatchType::Exact => None,
+            ValueMatchType::ThresholdOrAbove
+            | ValueMatchType::ThresholdOrBelow
+            | ValueMatchType::Range
+            | ValueMatchType::OutsideRange => self.events(u7::default()),
+            ValueMatchType::Exact => Vec::new(),
         }
     }
 
-    fn exact_hash_key_inner(&self) -> Option<LiveEvent> {
+    fn exact_hash_keys_inner(&self) -> Vec<LiveEvent<'_>> {
         if self.match_type == ValueMatchType::Exact {
-            return Some(LiveEvent::Midi {
-                channel: self.channel,
-                message: MidiMessage::NoteOff {
-                    key: self.note,
-                    vel: self.velocity,
-                },
-            });
+            return self.events(self.velocity);
         }
-        None
+        Vec::new()
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub(crate) struct TriggerAftertouch {
-    pub(crate) channel: u4,
-    pub(crate) note: u7,
+    #[serde(deserialize_with = "config::channels")]
+    pub(crate) channel: Vec<u4>,
+    #[serde(deserialize_with = "config::u7s")]
+    pub(crate) note: Vec<u7>,
+    #[serde(deserialize_with = "config::u7")]
     pub(crate) pressure: u7,
+    #[serde(default)]
     pub(crate) match_type: ValueMatchType,
+    #[serde(default, deserialize_with = "config::optional_u7")]
+    pub(crate) high: Option<u7>,
+}
+
+impl TriggerAftertouch {
+    fn events(&self, vel: u7) -> Vec<LiveEvent<'static>> {
+        for_each_number(&self.channel, &self.note, |channel, key| LiveEvent::Midi {
+            channel,
+            message: MidiMessage::Aftertouch { key, vel },
+        })
+    }
 }
 
 impl Trigger for TriggerAftertouch {
-    fn is_triggered_by(&self, event: &LiveEvent) -> bool {
+    fn triggered_by(&self, event: &LiveEvent) -> Option<TriggeredBy> {
         if let LiveEvent::Midi {
             channel,
             message: MidiMessage::Aftertouch { key, vel },
         } = event
         {
-            if self.channel == *channel && self.note == *key {
-                return match self.match_type {
-                    ValueMatchType::Exact => *vel == self.pressure,
-                    ValueMatchType::ThresholdOrAbove => *vel >= self.pressure,
-                    ValueMatchType::ThresholdOrBelow => *vel <= self.pressure,
-                };
+            if self.channel.contains(channel)
+                && self.note.contains(key)
+                && self.match_type.matches(*vel, self.pressure, self.high)
+            {
+                return Some(TriggeredBy {
+                    channel: Some(*channel),
+                    number: Some(*key),
+                    captured: None,
+                });
             }
         }
-        false
+        None
     }
-}
 
-impl Control for TriggerAftertouch {
-    fn handle_midi_event_inner(&self, event: &LiveEvent) {
-        if self.is_triggered_by(event) {
-            println!("TriggerAftertouch: {event:?}");
-        }
+    fn check(&self) -> Result<(), String> {
+        self.match_type.check(self.pressure, self.high)
     }
 
-    fn threshold_hash_key_inner(&self) -> Option<LiveEvent> {
+    fn threshold_hash_keys_inner(&self) -> Vec<LiveEvent<'_>> {
         match self.match_type {
-            ValueMatchType::ThresholdOrAbove | ValueMatchType::ThresholdOrBelow => {
-                Some(LiveEvent::Midi {
-                    channel: self.channel,
-                    message: MidiMessage::Aftertouch {
-                        key: self.note,
-                        vel: u7::default(),
-                    },
-                })
-            }
-            ValueMatchType::Exact => None,
+            ValueMatchType::ThresholdOrAbove
+            | ValueMatchType::ThresholdOrBelow
+            | ValueMatchType::Range
+            | ValueMatchType::OutsideRange => self.events(u7::default()),
+            ValueMatchType::Exact => Vec::new(),
         }
     }
 
-    fn exact_hash_key_inner(&self) -> Option<LiveEvent> {
+    fn exact_hash_keys_inner(&self) -> Vec<LiveEvent<'_>> {
         if self.match_type == ValueMatchType::Exact {
-            return Some(LiveEvent::Midi {
-                channel: self.channel,
-                message: MidiMessage::Aftertouch {
-                    key: self.note,
-                    vel: self.pressure,
-                },
-            });
+            return self.events(self.pressure);
         }
-        None
+        Vec::new()
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub(crate) struct TriggerController {
-    pub(crate) channel: u4,
-    pub(crate) controller: u7,
+    #[serde(deserialize_with = "config::channels")]
+    pub(crate) channel: Vec<u4>,
+    #[serde(deserialize_with = "config::u7s")]
+    pub(crate) controller: Vec<u7>,
+    #[serde(deserialize_with = "config::u7")]
     pub(crate) value: u7,
+    #[serde(default)]
     pub(crate) match_type: ValueMatchType,
+    #[serde(default, deserialize_with = "config::optional_u7")]
+    pub(crate) high: Option<u7>,
+}
+
+impl TriggerController {
+    fn events(&self, value: u7) -> Vec<LiveEvent<'static>> {
+        for_each_number(&self.channel, &self.controller, |channel, controller| {
+            LiveEvent::Midi {
+                channel,
+                message: MidiMessage::Controller { controller, value },
+            }
+        })
+    }
 }
 
 impl Trigger for TriggerController {
-    fn is_triggered_by(&self, event: &LiveEvent) -> bool {
+    fn triggered_by(&self, event: &LiveEvent) -> Option<TriggeredBy> {
         if let LiveEvent::Midi {
             channel,
             message: MidiMessage::Controller { controller, value },
         } = event
         {
-            if self.channel == *channel && self.controller == *controller {
-                return match self.match_type {
-                    ValueMatchType::Exact => *value == self.value,
-                    ValueMatchType::ThresholdOrAbove => *value >= self.value,
-                    ValueMatchType::ThresholdOrBelow => *value <= self.value,
-                };
+            if self.channel.contains(channel)
+                && self.controller.contains(controller)
+                && self.match_type.matches(*value, self.value, self.high)
+            {
+                return Some(TriggeredBy {
+                    channel: Some(*channel),
+                    number: Some(*controller),
+                    captured: None,
+                });
             }
         }
-        false
+        None
     }
-}
 
-impl Control for TriggerController {
-    fn handle_midi_event_inner(&self, event: &LiveEvent) {
-        if self.is_triggered_by(event) {
-            println!("TriggerController: {event:?}");
-        }
+    fn check(&self) -> Result<(), String> {
+        self.match_type.check(self.value, self.high)
     }
 
-    fn threshold_hash_key_inner(&self) -> Option<LiveEvent> {
+    fn threshold_hash_keys_inner(&self) -> Vec<LiveEvent<'_>> {
         match self.match_type {
-            ValueMatchType::ThresholdOrAbove | ValueMatchType::ThresholdOrBelow => {
-                Some(LiveEvent::Midi {
-                    channel: self.channel,
-                    message: MidiMessage::Controller {
-                        controller: self.controller,
-                        value: u7::default(),
-                    },
-                })
-            }
-            ValueMatchType::Exact => None,
+            ValueMatchType::ThresholdOrAbove
+            | ValueMatchType::ThresholdOrBelow
+            | ValueMatchType::Range
+            | ValueMatchType::OutsideRange => self.events(u7::default()),
+            ValueMatchType::Exact => Vec::new(),
         }
     }
 
-    fn exact_hash_key_inner(&self) -> Option<LiveEvent> {
+    fn exact_hash_keys_inner(&self) -> Vec<LiveEvent<'_>> {
         if self.match_type == ValueMatchType::Exact {
-            return Some(LiveEvent::Midi {
-                channel: self.channel,
-                message: MidiMessage::Controller {
-                    controller: self.controller,
-                    value: self.value,
-                },
-            });
+            return self.events(self.value);
         }
-        None
+        Vec::new()
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub(crate) struct TriggerProgramChange {
-    pub(crate) channel: u4,
+    #[serde(deserialize_with = "config::channels")]
+    pub(crate) channel: Vec<u4>,
+    #[serde(deserialize_with = "config::u7")]
     pub(crate) program: u7,
+    #[serde(default)]
     pub(crate) match_type: ValueMatchType,
+    #[serde(default, deserialize_with = "config::optional_u7")]
+    pub(crate) high: Option<u7>,
+}
+
+impl TriggerProgramChange {
+    fn events(&self, program: u7) -> Vec<LiveEvent<'static>> {
+        for_each_channel(&self.channel, |channel| LiveEvent::Midi {
+            channel,
+            message: MidiMessage::ProgramChange { program },
+        })
+    }
 }
 
 impl Trigger for TriggerProgramChange {
-    fn is_triggered_by(&self, event: &LiveEvent) -> bool {
+    fn triggered_by(&self, event: &LiveEvent) -> Option<TriggeredBy> {
         if let LiveEvent::Midi {
             channel,
             message: MidiMessage::ProgramChange { program },
         } = event
         {
-            if self.channel == *channel {
-                return match self.match_type {
-                    ValueMatchType::Exact => *program == self.program,
-                    ValueMatchType::ThresholdOrAbove => *program >= self.program,
-                    ValueMatchType::ThresholdOrBelow => *program <= self.program,
-                };
+            if self.channel.contains(channel)
+                && self.match_type.matches(*program, self.program, self.high)
+            {
+                return Some(TriggeredBy {
+                    channel: Some(*channel),
+                    number: None,
+                    captured: None,
+                });
             }
         }
-        false
+        None
     }
-}
 
-impl Control for TriggerProgramChange {
-    fn handle_midi_event_inner(&self, event: &LiveEvent) {
-        if self.is_triggered_by(event) {
-            println!("TriggerProgramChange: {event:?}");
-        }
+    fn check(&self) -> Result<(), String> {
+        self.match_type.check(self.program, self.high)
     }
 
-    fn threshold_hash_key_inner(&self) -> Option<LiveEvent> {
+    fn threshold_hash_keys_inner(&self) -> Vec<LiveEvent<'_>> {
         match self.match_type {
-            ValueMatchType::ThresholdOrAbove | ValueMatchType::ThresholdOrBelow => {
-                Some(LiveEvent::Midi {
-                    channel: self.channel,
-                    message: MidiMessage::ProgramChange {
-                        program: u7::default(),
-                    },
-                })
-            }
-            ValueMatchType::Exact => None,
+            ValueMatchType::ThresholdOrAbove
+            | ValueMatchType::ThresholdOrBelow
+            | ValueMatchType::Range
+            | ValueMatchType::OutsideRange => self.events(u7::default()),
+            ValueMatchType::Exact => Vec::new(),
         }
     }
 
-    fn exact_hash_key_inner(&self) -> Option<LiveEvent> {
+    fn exact_hash_keys_inner(&self) -> Vec<LiveEvent<'_>> {
         if self.match_type == ValueMatchType::Exact {
-            return Some(LiveEvent::Midi {
-                channel: self.channel,
-                message: MidiMessage::ProgramChange {
-                    program: self.program,
-                },
-            });
+            return self.events(self.program);
         }
-        None
+        Vec::new()
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub(crate) struct TriggerChannelAftertouch {
-    pub(crate) channel: u4,
+    #[serde(deserialize_with = "config::channels")]
+    pub(crate) channel: Vec<u4>,
+    #[serde(deserialize_with = "config::u7")]
     pub(crate) pressure: u7,
+    #[serde(default)]
     pub(crate) match_type: ValueMatchType,
+    #[serde(default, deserialize_with = "config::optional_u7")]
+    pub(crate) high: Option<u7>,
+}
+
+impl TriggerChannelAftertouch {
+    fn events(&self, vel: u7) -> Vec<LiveEvent<'static>> {
+        for_each_channel(&self.channel, |channel| LiveEvent::Midi {
+            channel,
+            message: MidiMessage::ChannelAftertouch { vel },
+        })
+    }
 }
 
 impl Trigger for TriggerChannelAftertouch {
-    fn is_triggered_by(&self, event: &LiveEvent) -> bool {
+    fn triggered_by(&self, event: &LiveEvent) -> Option<TriggeredBy> {
         if let LiveEvent::Midi {
             channel,
             message: MidiMessage::ChannelAftertouch { vel },
         } = event
         {
-            if self.channel == *channel {
-                return match self.match_type {
-                    ValueMatchType::Exact => *vel == self.pressure,
-                    ValueMatchType::ThresholdOrAbove => *vel >= self.pressure,
-                    ValueMatchType::ThresholdOrBelow => *vel <= self.pressure,
-                };
+            if self.channel.contains(channel)
+                && self.match_type.matches(*vel, self.pressure, self.high)
+            {
+                return Some(TriggeredBy {
+                    channel: Some(*channel),
+                    number: None,
+                    captured: None,
+                });
             }
         }
-        false
+        None
     }
-}
 
-impl Control for TriggerChannelAftertouch {
-    fn handle_midi_event_inner(&self, event: &LiveEvent) {
-        if self.is_triggered_by(event) {
-            println!("TriggerChannelAftertouch: {event:?}");
-        }
+    fn check(&self) -> Result<(), String> {
+        self.match_type.check(self.pressure, self.high)
     }
 
-    fn threshold_hash_key_inner(&self) -> Option<LiveEvent> {
+    fn threshold_hash_keys_inner(&self) -> Vec<LiveEvent<'_>> {
         match self.match_type {
-            ValueMatchType::ThresholdOrAbove | ValueMatchType::ThresholdOrBelow => {
-                Some(LiveEvent::Midi {
-                    channel: self.channel,
-                    message: MidiMessage::ChannelAftertouch { vel: u7::default() },
-                })
-            }
-            ValueMatchType::Exact => None,
+            ValueMatchType::ThresholdOrAbove
+            | ValueMatchType::ThresholdOrBelow
+            | ValueMatchType::Range
+            | ValueMatchType::OutsideRange => self.events(u7::default()),
+            ValueMatchType::Exact => Vec::new(),
         }
     }
 
-    fn exact_hash_key_inner(&self) -> Option<LiveEvent> {
+    fn exact_hash_keys_inner(&self) -> Vec<LiveEvent<'_>> {
         if self.match_type == ValueMatchType::Exact {
-            return Some(LiveEvent::Midi {
-                channel: self.channel,
-                message: MidiMessage::ChannelAftertouch { vel: self.pressure },
-            });
+            return self.events(self.pressure);
         }
-        None
+        Vec::new()
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub(crate) struct TriggerPitchBend {
-    pub(crate) channel: u4,
+    #[serde(deserialize_with = "config::channels")]
+    pub(crate) channel: Vec<u4>,
+    #[serde(deserialize_with = "config::pitch_bend")]
     pub(crate) value: i16,
+    #[serde(default)]
     pub(crate) match_type: ValueMatchType,
+    #[serde(default, deserialize_with = "config::optional_pitch_bend")]
+    pub(crate) high: Option<i16>,
+}
+
+impl TriggerPitchBend {
+    fn events(&self, bend: PitchBend) -> Vec<LiveEvent<'static>> {
+        for_each_channel(&self.channel, |channel| LiveEvent::Midi {
+            channel,
+            message: MidiMessage::PitchBend { bend },
+        })
+    }
 }
 
 impl Trigger for TriggerPitchBend {
-    fn is_triggered_by(&self, event: &LiveEvent) -> bool {
+    fn triggered_by(&self, event: &LiveEvent) -> Option<TriggeredBy> {
         if let LiveEvent::Midi {
             channel,
             message: MidiMessage::PitchBend { bend },
         } = event
         {
-            if self.channel == *channel {
-                return match self.match_type {
-                    ValueMatchType::Exact => bend.as_int() == self.value,
-                    ValueMatchType::ThresholdOrAbove => bend.as_int() >= self.value,
-                    ValueMatchType::ThresholdOrBelow => bend.as_int() <= self.value,
-                };
+            if self.channel.contains(channel)
+                && self
+                    .match_type
+                    .matches(bend.as_int(), self.value, self.high)
+            {
+                return Some(TriggeredBy {
+                    channel: Some(*channel),
+                    number: None,
+                    captured: None,
+                });
             }
         }
-        false
+        None
     }
-}
 
-impl Control for TriggerPitchBend {
-    fn handle_midi_event_inner(&self, event: &LiveEvent) {
-        if self.is_triggered_by(event) {
-            println!("TriggerPitchBend: {event:?}");
-        }
+    fn check(&self) -> Result<(), String> {
+        self.match_type.check(self.value, self.high)
     }
 
-    fn threshold_hash_key_inner(&self) -> Option<LiveEvent> {
+    fn threshold_hash_keys_inner(&self) -> Vec<LiveEvent<'_>> {
         match self.match_type {
-            ValueMatchType::ThresholdOrAbove | ValueMatchType::ThresholdOrBelow => {
-                Some(LiveEvent::Midi {
-                    channel: self.channel,
-                    message: MidiMessage::PitchBend {
-                        bend: PitchBend::mid_raw_value(),
-                    },
-                })
-            }
-            ValueMatchType::Exact => None,
+            ValueMatchType::ThresholdOrAbove
+            | ValueMatchType::ThresholdOrBelow
+            | ValueMatchType::Range
+            | ValueMatchType::OutsideRange => self.events(PitchBend::mid_raw_value()),
+            ValueMatchType::Exact => Vec::new(),
         }
     }
 
-    fn exact_hash_key_inner(&self) -> Option<LiveEvent> {
+    fn exact_hash_keys_inner(&self) -> Vec<LiveEvent<'_>> {
         if self.match_type == ValueMatchType::Exact {
-            return Some(LiveEvent::Midi {
-                channel: self.channel,
-                message: MidiMessage::PitchBend {
-                    bend: PitchBend::from_int(self.value),
-                },
-            });
+            return self.events(PitchBend::from_int(self.value));
         }
-        None
+        Vec::new()
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub(crate) struct TriggerMtcQuarterFrame {
+    #[serde(deserialize_with = "config::mtc_quarter_frame_message")]
     pub(crate) message: MtcQuarterFrameMessage,
+    #[serde(deserialize_with = "config::u4")]
     pub(crate) value: u4,
+    #[serde(default)]
     pub(crate) match_type: ValueMatchType,
+    #[serde(default, deserialize_with = "config::optional_u4")]
+    pub(crate) high: Option<u4>,
 }
 
 impl Trigger for TriggerMtcQuarterFrame {
-    fn is_triggered_by(&self, event: &LiveEvent) -> bool {
+    fn triggered_by(&self, event: &LiveEvent) -> Option<TriggeredBy> {
         if let LiveEvent::Common(SystemCommon::MidiTimeCodeQuarterFrame(message, value)) = event {
             if *message == self.message {
-                return match self.match_type {
-                    ValueMatchType::Exact => *value == self.value,
-                    ValueMatchType::ThresholdOrAbove => *value >= self.value,
-                    ValueMatchType::ThresholdOrBelow => *value <= self.value,
-                };
+                return self
+                    .match_type
+                    .matches(*value, self.value, self.high)
+                    .then(TriggeredBy::default);
             }
         }
-        false
+        None
     }
-}
 
-impl Control for TriggerMtcQuarterFrame {
-    fn handle_midi_event_inner(&self, event: &LiveEvent) {
-        if self.is_triggered_by(event) {
-            println!("TriggerMtcQuarterFrame: {event:?}");
-        }
+    fn check(&self) -> Result<(), String> {
+        self.match_type.check(self.value, self.high)
     }
 
-    fn threshold_hash_key_inner(&self) -> Option<LiveEvent> {
+    fn threshold_hash_keys_inner(&self) -> Vec<LiveEvent<'_>> {
         match self.match_type {
-            ValueMatchType::ThresholdOrAbove | ValueMatchType::ThresholdOrBelow => {
-                Some(LiveEvent::Common(SystemCommon::MidiTimeCodeQuarterFrame(
-                    self.message,
-                    u4::default(),
-                )))
-            }
-            ValueMatchType::Exact => None,
+            ValueMatchType::ThresholdOrAbove
+            | ValueMatchType::ThresholdOrBelow
+            | ValueMatchType::Range
+            | ValueMatchType::OutsideRange => vec![LiveEvent::Common(
+                SystemCommon::MidiTimeCodeQuarterFrame(self.message, u4::default()),
+            )],
+            ValueMatchType::Exact => Vec::new(),
         }
     }
 
-    fn exact_hash_key_inner(&self) -> Option<LiveEvent> {
+    fn exact_hash_keys_inner(&self) -> Vec<LiveEvent<'_>> {
         if self.match_type == ValueMatchType::Exact {
-            return Some(LiveEvent::Common(SystemCommon::MidiTimeCodeQuarterFrame(
+            return vec![LiveEvent::Common(SystemCommon::MidiTimeCodeQuarterFrame(
                 self.message,
                 self.value,
-            )));
+            ))];
         }
-        None
+        Vec::new()
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub(crate) struct TriggerSongPosition {
+    #[serde(deserialize_with = "config::u14")]
     pub(crate) position: u14,
+    #[serde(default)]
     pub(crate) match_type: ValueMatchType,
+    #[serde(default, deserialize_with = "config::optional_u14")]
+    pub(crate) high: Option<u14>,
 }
 
 impl Trigger for TriggerSongPosition {
-    fn is_triggered_by(&self, event: &LiveEvent) -> bool {
+    fn triggered_by(&self, event: &LiveEvent) -> Option<TriggeredBy> {
         if let LiveEvent::Common(SystemCommon::SongPosition(position)) = event {
-            return match self.match_type {
-                ValueMatchType::Exact => *position == self.position,
-                ValueMatchType::ThresholdOrAbove => *position >= self.position,
-                ValueMatchType::ThresholdOrBelow => *position <= self.position,
-            };
+            return self
+                .match_type
+                .matches(*position, self.position, self.high)
+                .then(TriggeredBy::default);
         }
-        false
+        None
     }
-}
 
-impl Control for TriggerSongPosition {
-    fn handle_midi_event_inner(&self, event: &LiveEvent) {
-        if self.is_triggered_by(event) {
-            println!("TriggerSongPosition: {event:?}");
-        }
+    fn check(&self) -> Result<(), String> {
+        self.match_type.check(self.position, self.high)
     }
 
-    fn threshold_hash_key_inner(&self) -> Option<LiveEvent> {
+    fn threshold_hash_keys_inner(&self) -> Vec<LiveEvent<'_>> {
         match self.match_type {
-            ValueMatchType::ThresholdOrAbove | ValueMatchType::ThresholdOrBelow => Some(
-                LiveEvent::Common(SystemCommon::SongPosition(u14::default())),
-            ),
-            ValueMatchType::Exact => None,
+            ValueMatchType::ThresholdOrAbove
+            | ValueMatchType::ThresholdOrBelow
+            | ValueMatchType::Range
+            | ValueMatchType::OutsideRange => vec![LiveEvent::Common(SystemCommon::SongPosition(
+                u14::default(),
+            ))],
+            ValueMatchType::Exact => Vec::new(),
         }
     }
 
-    fn exact_hash_key_inner(&self) -> Option<LiveEvent> {
+    fn exact_hash_keys_inner(&self) -> Vec<LiveEvent<'_>> {
         if self.match_type == ValueMatchType::Exact {
-            return Some(LiveEvent::Common(SystemCommon::SongPosition(self.position)));
+            return vec![LiveEvent::Common(SystemCommon::SongPosition(self.position))];
         }
-        None
+        Vec::new()
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub(crate) struct TriggerSongSelect {
+    #[serde(deserialize_with = "config::u7")]
     pub(crate) song: u7,
+    #[serde(default)]
     pub(crate) match_type: ValueMatchType,
+    #[serde(default, deserialize_with = "config::optional_u7")]
+    pub(crate) high: Option<u7>,
 }
 
 impl Trigger for TriggerSongSelect {
-    fn is_triggered_by(&self, event: &LiveEvent) -> bool {
+    fn triggered_by(&self, event: &LiveEvent) -> Option<TriggeredBy> {
         if let LiveEvent::Common(SystemCommon::SongSelect(song)) = event {
-            return match self.match_type {
-                ValueMatchType::Exact => *song == self.song,
-                ValueMatchType::ThresholdOrAbove => *song >= self.song,
-                ValueMatchType::ThresholdOrBelow => *song <= self.song,
-            };
+            return self
+                .match_type
+                .matches(*song, self.song, self.high)
+                .then(TriggeredBy::default);
         }
-        false
+        None
     }
-}
 
-impl Control for TriggerSongSelect {
-    fn handle_midi_event_inner(&self, event: &LiveEvent) {
-        if self.is_triggered_by(event) {
-            println!("TriggerSongSelect: {event:?}");
-        }
+    fn check(&self) -> Result<(), String> {
+        self.match_type.check(self.song, self.high)
     }
 
-    fn threshold_hash_key_inner(&self) -> Option<LiveEvent> {
+    fn threshold_hash_keys_inner(&self) -> Vec<LiveEvent<'_>> {
         match self.match_type {
-            ValueMatchType::ThresholdOrAbove | ValueMatchType::ThresholdOrBelow => {
-                Some(LiveEvent::Common(SystemCommon::SongSelect(u7::default())))
+            ValueMatchType::ThresholdOrAbove
+            | ValueMatchType::ThresholdOrBelow
+            | ValueMatchType::Range
+            | ValueMatchType::OutsideRange => {
+                vec![LiveEvent::Common(SystemCommon::SongSelect(u7::default()))]
             }
-            ValueMatchType::Exact => None,
+            ValueMatchType::Exact => Vec::new(),
         }
     }
 
-    fn exact_hash_key_inner(&self) -> Option<LiveEvent> {
+    fn exact_hash_keys_inner(&self) -> Vec<LiveEvent<'_>> {
         if self.match_type == ValueMatchType::Exact {
-            return Some(LiveEvent::Common(SystemCommon::SongSelect(self.song)));
+            return vec![LiveEvent::Common(SystemCommon::SongSelect(self.song))];
         }
-        None
+        Vec::new()
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub(crate) struct TriggerTuneRequest {}
 
 impl Trigger for TriggerTuneRequest {
-    fn is_triggered_by(&self, event: &LiveEvent) -> bool {
-        matches!(event, LiveEvent::Common(SystemCommon::TuneRequest))
+    fn triggered_by(&self, event: &LiveEvent) -> Option<TriggeredBy> {
+        matches!(event, LiveEvent::Common(SystemCommon::TuneRequest)).then(TriggeredBy::default)
+    }
+
+    fn threshold_hash_keys_inner(&self) -> Vec<LiveEvent<'_>> {
+        Vec::new()
     }
+
+    fn exact_hash_keys_inner(&self) -> Vec<LiveEvent<'_>> {
+        vec![LiveEvent::Common(SystemCommon::TuneRequest)]
+    }
+}
+
+/// One byte of a SysEx pattern, the bits that are set in `mask` have to match
+/// `value`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) struct SysExByte {
+    pub(crate) value: u8,
+    pub(crate) mask: u8,
+    /// Matches any value, which ends up in the value of the trigger
+    pub(crate) capture: bool,
+}
+
+/// What the bytes after the manufacturer ID have to look like. Written as
+/// hex bytes separated by spaces, where `?` stands in for any hex digit, `vv`
+/// for a byte that gets captured, and a `*` at the end for any number of
+/// bytes after that, e.g. `"10 4? vv *"`.
+#[derive(Debug, Eq, PartialEq)]
+pub(crate) struct SysExPattern {
+    pub(crate) bytes: Vec<SysExByte>,
+    /// Whether more bytes may follow the pattern
+    pub(crate) open_ended: bool,
 }
 
-impl Control for TriggerTuneRequest {
-    fn handle_midi_event_inner(&self, event: &LiveEvent) {
-        if self.is_triggered_by(event) {
-            println!("TriggerTuneRequest: {event:?}");
+impl Default for SysExPattern {
+    /// Anything goes
+    fn default() -> Self {
+        Self {
+            bytes: Vec::new(),
+            open_ended: true,
         }
     }
+}
 
-    fn threshold_hash_key_inner(&self) -> Option<LiveEvent> {
-        None
+impl FromStr for SysExPattern {
+    type Err = String;
+
+    fn from_str(pattern: &str) -> Result<Self, Self::Err> {
+        let mut tokens = pattern.split_whitespace().peekable();
+        let mut bytes = Vec::new();
+        let mut open_ended = false;
+        while let Some(token) = tokens.next() {
+            if token == "*" {
+                if tokens.peek().is_some() {
+                    return Err("`*` can only go at the end of a SysEx pattern".to_string());
+                }
+                open_ended = true;
+                break;
+            }
+            if token.eq_ignore_ascii_case("vv") {
+                bytes.push(SysExByte {
+                    value: 0,
+                    mask: 0,
+                    capture: true,
+                });
+                continue;
+            }
+            let mut byte = SysExByte {
+                value: 0,
+                mask: 0,
+                capture: false,
+            };
+            let digits: Vec<char> = token.chars().collect();
+            if digits.len() != 2 {
+                return Err(format!("{token:?} in SysEx pattern isn't a byte"));
+            }
+            for digit in digits {
+                byte.value <<= 4;
+                byte.mask <<= 4;
+                if digit != '?' {
+                    let Some(nibble) = digit.to_digit(16) else {
+                        return Err(format!("{token:?} in SysEx pattern isn't a byte"));
+                    };
+                    // A single hex digit, so this can't truncate
+                    #[allow(clippy::cast_possible_truncation)]
+                    let nibble = nibble as u8;
+                    byte.value |= nibble;
+                    byte.mask |= 0xF;
+                }
+            }
+            if byte.value > 0x7F {
+                return Err(format!(
+                    "{token:?} in SysEx pattern is above 7F, which SysEx data can't be"
+                ));
+            }
+            bytes.push(byte);
+        }
+        if bytes.iter().filter(|byte| byte.capture).count() > 4 {
+            return Err("a SysEx pattern can capture 4 bytes at most".to_string());
+        }
+        Ok(Self { bytes, open_ended })
     }
+}
 
-    fn exact_hash_key_inner(&self) -> Option<LiveEvent> {
-        Some(LiveEvent::Common(SystemCommon::TuneRequest))
+impl SysExPattern {
+    /// Whether `payload` matches, with the captured value if there is one
+    fn matches(&self, payload: &[u7]) -> Option<TriggeredBy> {
+        let length_matches = if self.open_ended {
+            payload.len() >= self.bytes.len()
+        } else {
+            payload.len() == self.bytes.len()
+        };
+        if !length_matches {
+            return None;
+        }
+        let mut captured = 0_u32;
+        let mut captured_bits = 0;
+        for (byte, data) in self.bytes.iter().zip(payload) {
+            let data = data.as_int();
+            if byte.capture {
+                // Data bytes only carry 7 bits each, so pack them like
+                // 14-bit values are packed
+                captured = captured << 7 | u32::from(data);
+                captured_bits += 7;
+            } else if data & byte.mask != byte.value & byte.mask {
+                return None;
+            }
+        }
+        #[allow(clippy::cast_precision_loss)]
+        let captured = (captured_bits > 0).then(|| Captured {
+            raw: captured,
+            value: captured as f32 / ((1_u32 << captured_bits) - 1) as f32,
+        });
+        Some(TriggeredBy {
+            captured,
+            ..TriggeredBy::default()
+        })
     }
 }
 
-#[derive(Debug)]
-pub(crate) struct TriggerTimingClock {}
-
-impl Trigger for TriggerTimingClock {
-    fn is_triggered_by(&self, event: &LiveEvent) -> bool {
-        matches!(event, LiveEvent::Realtime(SystemRealtime::TimingClock))
+/// The length of the manufacturer ID at the start of SysEx data, which is a
+/// single byte unless that byte is 0.
+fn manufacturer_id_length(data: &[u7]) -> usize {
+    match data.first() {
+        Some(first) if first.as_int() == 0 => data.len().min(3),
+        _ => data.len().min(1),
     }
 }
 
-impl Control for TriggerTimingClock {
-    fn handle_midi_event_inner(&self, event: &LiveEvent) {
-        if self.is_triggered_by(event) {
-            println!("TriggerTimingClock: {event:?}");
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct TriggerSysEx {
+    /// A single byte, or three starting with 0
+    #[serde(deserialize_with = "config::manufacturer_id")]
+    pub(crate) manufacturer: Vec<u7>,
+    /// The bytes after `manufacturer`, any of them if there's no pattern
+    #[serde(default, deserialize_with = "config::sysex_pattern")]
+    pub(crate) pattern: SysExPattern,
+}
+
+impl Trigger for TriggerSysEx {
+    fn triggered_by(&self, event: &LiveEvent) -> Option<TriggeredBy> {
+        if let LiveEvent::Common(SystemCommon::SysEx(data)) = event {
+            let (manufacturer, payload) = data.split_at(manufacturer_id_length(data));
+            if manufacturer == self.manufacturer.as_slice() {
+                return self.pattern.matches(payload);
+            }
         }
+        None
     }
 
-    fn threshold_hash_key_inner(&self) -> Option<LiveEvent> {
-        None
+    fn threshold_hash_keys_inner(&self) -> Vec<LiveEvent<'_>> {
+        // Patterns can't be hashed, so register under the manufacturer ID,
+        // which is what `live_event_without_value` leaves of SysEx messages,
+        // and look at the rest once we get there.
+        vec![LiveEvent::Common(SystemCommon::SysEx(&self.manufacturer))]
     }
 
-    fn exact_hash_key_inner(&self) -> Option<LiveEvent> {
-        Some(LiveEvent::Realtime(SystemRealtime::TimingClock))
+    fn exact_hash_keys_inner(&self) -> Vec<LiveEvent<'_>> {
+        Vec::new()
     }
 }
 
-#[derive(Debug)]
-pub(crate) struct TriggerStart {}
+/// Several buttons held down at the same time. Fires when the last of them
+/// gets pressed, if that's within `within_ms` of the first. From then on
+/// until all of them are released, their messages go to the chord only,
+/// so that their own controls don't act on them. Before that, their own
+/// controls get a key press only once `within_ms` has passed without it
+/// becoming part of the chord.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct TriggerChord {
+    pub(crate) keys: Vec<ButtonSource>,
+    #[serde(default = "TriggerChord::default_within_ms")]
+    pub(crate) within_ms: u64,
+    /// Indices into `keys`, with the timestamps they were pressed at
+    #[serde(skip)]
+    held: RefCell<Vec<(usize, u64)>>,
+    /// Fired, and not all keys are released yet
+    #[serde(skip)]
+    engaged: Cell<bool>,
+    /// Whether the last message `track`ed fired the chord
+    #[serde(skip)]
+    completed: Cell<bool>,
+}
 
-impl Trigger for TriggerStart {
-    fn is_triggered_by(&self, event: &LiveEvent) -> bool {
-        matches!(event, LiveEvent::Realtime(SystemRealtime::Start))
+impl TriggerChord {
+    fn default_within_ms() -> u64 {
+        50
+    }
+
+    /// The index of the key `event` is for, and whether it presses it
+    fn key(&self, event: &LiveEvent) -> Option<(usize, bool)> {
+        self.keys
+            .iter()
+            .enumerate()
+            .find_map(|(index, key)| Some((index, key.pressed(event)?)))
+    }
+
+    /// Whether pressing the key at `index` at `timestamp` fires the chord
+    fn completes(&self, index: usize, timestamp: u64) -> bool {
+        if self.engaged.get() {
+            return false;
+        }
+        let held = self.held.borrow();
+        let others: Vec<u64> = held
+            .iter()
+            .filter(|(held_index, _)| *held_index != index)
+            .map(|(_, pressed_at)| *pressed_at)
+            .collect();
+        others.len() == self.keys.len() - 1
+            && others
+                .iter()
+                .min()
+                .is_some_and(|first| timestamp.saturating_sub(*first) <= self.within_ms * 1000)
     }
 }
 
-impl Control for TriggerStart {
-    fn handle_midi_event_inner(&self, event: &LiveEvent) {
-        if self.is_triggered_by(event) {
-            println!("TriggerStart: {event:?}");
+impl Trigger for TriggerChord {
+    fn triggered_by(&self, _event: &LiveEvent) -> Option<TriggeredBy> {
+        self.completed.get().then(TriggeredBy::default)
+    }
+
+    fn check(&self) -> Result<(), String> {
+        if self.keys.len() < 2 {
+            return Err("a chord needs at least 2 keys".to_string());
+        }
+        for (index, key) in self.keys.iter().enumerate() {
+            if self.keys[..index].contains(key) {
+                return Err(format!("{key:?} is in the chord more than once"));
+            }
+        }
+        Ok(())
+    }
+
+    fn track(&self, event: &LiveEvent, timestamp: u64, context: &mut Context) {
+        self.completed.set(false);
+        let Some((index, pressed)) = self.key(event) else {
+            return;
+        };
+        let completes = pressed && self.completes(index, timestamp);
+        let mut held = self.held.borrow_mut();
+        held.retain(|(held_index, _)| *held_index != index);
+        if pressed {
+            held.push((index, timestamp));
+        }
+        if completes {
+            self.completed.set(true);
+            self.engaged.set(true);
+            for key in &self.keys {
+                context.set_held_by_chord(key, true);
+            }
+        } else if held.is_empty() && self.engaged.replace(false) {
+            for key in &self.keys {
+                context.set_held_by_chord(key, false);
+            }
         }
     }
 
-    fn threshold_hash_key_inner(&self) -> Option<LiveEvent> {
-        None
+    fn captures(&self, event: &LiveEvent, timestamp: u64) -> bool {
+        self.key(event).is_some_and(|(index, pressed)| {
+            self.engaged.get() || (pressed && self.completes(index, timestamp))
+        })
+    }
+
+    fn holds_back(&self, event: &LiveEvent) -> Option<Duration> {
+        let (_, pressed) = self.key(event)?;
+        (pressed && !self.engaged.get()).then(|| Duration::from_millis(self.within_ms))
     }
 
-    fn exact_hash_key_inner(&self) -> Option<LiveEvent> {
-        Some(LiveEvent::Realtime(SystemRealtime::Start))
+    fn threshold_hash_keys_inner(&self) -> Vec<LiveEvent<'_>> {
+        self.keys.iter().flat_map(ButtonSource::hash_keys).collect()
+    }
+
+    fn exact_hash_keys_inner(&self) -> Vec<LiveEvent<'_>> {
+        Vec::new()
     }
 }
 
-#[derive(Debug)]
-pub(crate) struct TriggerContinue {}
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct TriggerTimingClock {}
 
-impl Trigger for TriggerContinue {
-    fn is_triggered_by(&self, event: &LiveEvent) -> bool {
-        matches!(event, LiveEvent::Realtime(SystemRealtime::Continue))
+impl Trigger for TriggerTimingClock {
+    fn triggered_by(&self, event: &LiveEvent) -> Option<TriggeredBy> {
+        matches!(event, LiveEvent::Realtime(SystemRealtime::TimingClock)).then(TriggeredBy::default)
+    }
+
+    fn threshold_hash_keys_inner(&self) -> Vec<LiveEvent<'_>> {
+        Vec::new()
+    }
+
+    fn exact_hash_keys_inner(&self) -> Vec<LiveEvent<'_>> {
+        vec![LiveEvent::Realtime(SystemRealtime::TimingClock)]
     }
 }
 
-impl Control for TriggerContinue {
-    fn handle_midi_event_inner(&self, event: &LiveEvent) {
-        if self.is_triggered_by(event) {
-            println!("TriggerContinue: {event:?}");
-        }
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct TriggerStart {}
+
+impl Trigger for TriggerStart {
+    fn triggered_by(&self, event: &LiveEvent) -> Option<TriggeredBy> {
+        matches!(event, LiveEvent::Realtime(SystemRealtime::Start)).then(TriggeredBy::default)
     }
 
-    fn threshold_hash_key_inner(&self) -> Option<LiveEvent> {
-        None
+    fn threshold_hash_keys_inner(&self) -> Vec<LiveEvent<'_>> {
+        Vec::new()
     }
 
-    fn exact_hash_key_inner(&self) -> Option<LiveEvent> {
-        Some(LiveEvent::Realtime(SystemRealtime::Continue))
+    fn exact_hash_keys_inner(&self) -> Vec<LiveEvent<'_>> {
+        vec![LiveEvent::Realtime(SystemRealtime::Start)]
     }
 }
 
-#[derive(Debug)]
-pub(crate) struct TriggerStop {}
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct TriggerContinue {}
 
-impl Trigger for TriggerStop {
-    fn is_triggered_by(&self, event: &LiveEvent) -> bool {
-        matches!(event, LiveEvent::Realtime(SystemRealtime::Stop))
+impl Trigger for TriggerContinue {
+    fn triggered_by(&self, event: &LiveEvent) -> Option<TriggeredBy> {
+        matches!(event, LiveEvent::Realtime(SystemRealtime::Continue)).then(TriggeredBy::default)
+    }
+
+    fn threshold_hash_keys_inner(&self) -> Vec<LiveEvent<'_>> {
+        Vec::new()
+    }
+
+    fn exact_hash_keys_inner(&self) -> Vec<LiveEvent<'_>> {
+        vec![LiveEvent::Realtime(SystemRealtime::Continue)]
     }
 }
 
-impl Control for TriggerStop {
-    fn handle_midi_event_inner(&self, event: &LiveEvent) {
-        if self.is_triggered_by(event) {
-            println!("TriggerStop: {event:?}");
-        }
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct TriggerStop {}
+
+impl Trigger for TriggerStop {
+    fn triggered_by(&self, event: &LiveEvent) -> Option<TriggeredBy> {
+        matches!(event, LiveEvent::Realtime(SystemRealtime::Stop)).then(TriggeredBy::default)
     }
 
-    fn threshold_hash_key_inner(&self) -> Option<LiveEvent> {
-        None
+    fn threshold_hash_keys_inner(&self) -> Vec<LiveEvent<'_>> {
+        Vec::new()
     }
 
-    fn exact_hash_key_inner(&self) -> Option<LiveEvent> {
-        Some(LiveEvent::Realtime(SystemRealtime::Stop))
+    fn exact_hash_keys_inner(&self) -> Vec<LiveEvent<'_>> {
+        vec![LiveEvent::Realtime(SystemRealtime::Stop)]
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub(crate) struct TriggerActiveSensing {}
 
 impl Trigger for TriggerActiveSensing {
-    fn is_triggered_by(&self, event: &LiveEvent) -> bool {
+    fn triggered_by(&self, event: &LiveEvent) -> Option<TriggeredBy> {
         matches!(event, LiveEvent::Realtime(SystemRealtime::ActiveSensing))
-    }
-}
-
-impl Control for TriggerActiveSensing {
-    fn handle_midi_event_inner(&self, event: &LiveEvent) {
-        if self.is_triggered_by(event) {
-            println!("TriggerActiveSensing: {event:?}");
-        }
+            .then(TriggeredBy::default)
     }
 
-    fn threshold_hash_key_inner(&self) -> Option<LiveEvent> {
-        None
+    fn threshold_hash_keys_inner(&self) -> Vec<LiveEvent<'_>> {
+        Vec::new()
     }
 
-    fn exact_hash_key_inner(&self) -> Option<LiveEvent> {
-        Some(LiveEvent::Realtime(SystemRealtime::ActiveSensing))
+    fn exact_hash_keys_inner(&self) -> Vec<LiveEvent<'_>> {
+        vec![LiveEvent::Realtime(SystemRealtime::ActiveSensing)]
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub(crate) struct TriggerReset {}
 
 impl Trigger for TriggerReset {
-    fn is_triggered_by(&self, event: &LiveEvent) -> bool {
-        matches!(event, LiveEvent::Realtime(SystemRealtime::Reset))
+    fn triggered_by(&self, event: &LiveEvent) -> Option<TriggeredBy> {
+        matches!(event, LiveEvent::Realtime(SystemRealtime::Reset)).then(TriggeredBy::default)
     }
-}
 
-impl Control for TriggerReset {
-    fn handle_midi_event_inner(&self, event: &LiveEvent) {
-        if self.is_triggered_by(event) {
-            println!("TriggerReset: {event:?}");
-        }
-    }
-
-    fn threshold_hash_key_inner(&self) -> Option<LiveEvent> {
-        None
+    fn threshold_hash_keys_inner(&self) -> Vec<LiveEvent<'_>> {
+        Vec::new()
     }
 
-    fn exact_hash_key_inner(&self) -> Option<LiveEvent> {
-        Some(LiveEvent::Realtime(SystemRealtime::Reset))
+    fn exact_hash_keys_inner(&self) -> Vec<LiveEvent<'_>> {
+        vec![LiveEvent::Realtime(SystemRealtime::Reset)]
     }
 }
 
-#[enum_dispatch(Control)]
-#[derive(Debug)]
+#[enum_dispatch(Trigger)]
+#[derive(Debug, Deserialize)]
+#[serde(remote = "Self", rename_all = "snake_case")]
 pub(crate) enum TriggerMidiMessage {
     // MIDI
     NoteOn(TriggerNoteOn),
@@ -778,6 +1156,9 @@ pub(crate) enum TriggerMidiMessage {
     SongPosition(TriggerSongPosition),
     SongSelect(TriggerSongSelect),
     TuneRequest(TriggerTuneRequest),
+    #[serde(rename = "sysex")]
+    SysEx(TriggerSysEx),
+    Chord(TriggerChord),
     // System Real-Time
     TimingClock(TriggerTimingClock),
     Start(TriggerStart),
@@ -787,8 +1168,54 @@ pub(crate) enum TriggerMidiMessage {
     Reset(TriggerReset),
 }
 
-pub(crate) fn live_event_without_value(event: &[u8]) -> MidiBytes {
-    let mut event = LiveEvent::parse(event).unwrap();
+config::tagged_enum!(TriggerMidiMessage, "message");
+
+impl TriggerMidiMessage {
+    /// The messages that turn the LEDs of the buttons sending this on or
+    /// off, for the kinds of messages that buttons send.
+    pub(crate) fn led_events(&self, on: bool) -> Vec<LiveEvent<'static>> {
+        let vel = if on { u7::max_value() } else { u7::default() };
+        match self {
+            TriggerMidiMessage::NoteOn(TriggerNoteOn { channel, note, .. })
+            | TriggerMidiMessage::NoteOff(TriggerNoteOff { channel, note, .. }) => {
+                for_each_number(channel, note, |channel, key| LiveEvent::Midi {
+                    channel,
+                    message: MidiMessage::NoteOn { key, vel },
+                })
+            }
+            TriggerMidiMessage::Controller(TriggerController {
+                channel,
+                controller,
+                ..
+            }) => for_each_number(channel, controller, |channel, controller| LiveEvent::Midi {
+                channel,
+                message: MidiMessage::Controller {
+                    controller,
+                    value: vel,
+                },
+            }),
+            TriggerMidiMessage::Chord(TriggerChord { keys, .. }) => {
+                keys.iter().map(|key| key.led_event(on)).collect()
+            }
+            _ => Vec::new(),
+        }
+    }
+}
+
+/// Deserializes a trigger, and `check`s it while there's still a position in
+/// the config to point to.
+pub(crate) fn checked<'de, D>(deserializer: D) -> Result<TriggerMidiMessage, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    // The tagged one, not the inherent one that `remote` derives
+    let trigger = <TriggerMidiMessage as Deserialize>::deserialize(deserializer)?;
+    trigger.check().map_err(de::Error::custom)?;
+    Ok(trigger)
+}
+
+pub(crate) fn live_event_without_value(event: &LiveEvent) -> MidiBytes {
+    let mut event = *event;
     match event {
         LiveEvent::Midi {
             channel: _,
@@ -818,9 +1245,350 @@ pub(crate) fn live_event_without_value(event: &[u8]) -> MidiBytes {
             SystemCommon::MidiTimeCodeQuarterFrame(_, ref mut value) => *value = u4::default(),
             SystemCommon::SongPosition(ref mut value) => *value = u14::default(),
             SystemCommon::SongSelect(ref mut value) => *value = u7::default(),
+            // Everything after the manufacturer ID counts as the value
+            SystemCommon::SysEx(ref mut data) => {
+                let whole = *data;
+                *data = &whole[..manufacturer_id_length(whole)];
+            }
             _ => (),
         },
         LiveEvent::Realtime(_) => (),
     }
     event.into()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A trigger as it appears in the config, `check`ed
+    fn trigger(toml: &str) -> Result<TriggerMidiMessage, String> {
+        #[derive(Deserialize)]
+        struct Wrapper {
+            #[serde(deserialize_with = "checked")]
+            command: TriggerMidiMessage,
+        }
+        toml::from_str::<Wrapper>(&format!("command = {toml}"))
+            .map(|wrapper| wrapper.command)
+            .map_err(|error| error.message().to_string())
+    }
+
+    fn note_on(channel: u8, key: u8, vel: u8) -> LiveEvent<'static> {
+        LiveEvent::Midi {
+            channel: u4::new(channel),
+            message: MidiMessage::NoteOn {
+                key: u7::new(key),
+                vel: u7::new(vel),
+            },
+        }
+    }
+
+    fn controller(channel: u8, controller: u8, value: u8) -> LiveEvent<'static> {
+        LiveEvent::Midi {
+            channel: u4::new(channel),
+            message: MidiMessage::Controller {
+                controller: u7::new(controller),
+                value: u7::new(value),
+            },
+        }
+    }
+
+    #[test]
+    fn value_match_types() {
+        use ValueMatchType::*;
+        let cases = [
+            (Exact, None, [false, true, false, false, false]),
+            (ThresholdOrAbove, None, [false, true, true, true, true]),
+            (ThresholdOrBelow, None, [true, true, false, false, false]),
+            (Range, Some(0x60), [false, true, true, true, false]),
+            (OutsideRange, Some(0x60), [true, false, false, false, true]),
+        ];
+        for (match_type, high, expected) in cases {
+            for (value, expected) in [0x3F, 0x40, 0x50, 0x60, 0x61].into_iter().zip(expected) {
+                assert_eq!(
+                    match_type.matches(value, 0x40, high),
+                    expected,
+                    "{match_type:?} with {value:#04X}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn value_match_type_checks() {
+        use ValueMatchType::*;
+        assert!(Exact.check(0x40, None).is_ok());
+        assert!(ThresholdOrAbove.check(0x40, None).is_ok());
+        assert!(Exact.check(0x40, Some(0x50)).is_err());
+        assert!(ThresholdOrBelow.check(0x40, Some(0x50)).is_err());
+        assert!(Range.check(0x40, None).is_err());
+        assert!(OutsideRange.check(0x40, None).is_err());
+        assert!(Range.check(0x40, Some(0x40)).is_ok());
+        assert!(Range.check(0x40, Some(0x3F)).is_err());
+        assert!(OutsideRange.check(0x40, Some(0x3F)).is_err());
+    }
+
+    #[test]
+    fn range_trigger() {
+        let trigger = trigger(
+            r#"{ message = "note_on", channel = 0, note = 0x24, velocity = 0x40, match_type = "range", high = 0x5F }"#,
+        )
+        .unwrap();
+        assert!(trigger.triggered_by(&note_on(0, 0x24, 0x3F)).is_none());
+        assert!(trigger.triggered_by(&note_on(0, 0x24, 0x40)).is_some());
+        assert!(trigger.triggered_by(&note_on(0, 0x24, 0x5F)).is_some());
+        assert!(trigger.triggered_by(&note_on(0, 0x24, 0x60)).is_none());
+        // Every value in the range has to be looked at, so there is no exact
+        // key to register under
+        assert!(trigger.exact_hash_keys_inner().is_empty());
+        assert_eq!(trigger.threshold_hash_keys_inner(), [note_on(0, 0x24, 0)]);
+    }
+
+    #[test]
+    fn outside_range_trigger() {
+        let trigger = trigger(
+            r#"{ message = "controller", channel = 0, controller = 7, value = 0x10, match_type = "outside_range", high = 0x70 }"#,
+        )
+        .unwrap();
+        assert!(trigger.triggered_by(&controller(0, 7, 0x0F)).is_some());
+        assert!(trigger.triggered_by(&controller(0, 7, 0x10)).is_none());
+        assert!(trigger.triggered_by(&controller(0, 7, 0x70)).is_none());
+        assert!(trigger.triggered_by(&controller(0, 7, 0x71)).is_some());
+    }
+
+    #[test]
+    fn range_trigger_checks() {
+        let error = trigger(
+            r#"{ message = "note_on", channel = 0, note = 0x24, velocity = 0x40, match_type = "range" }"#,
+        )
+        .unwrap_err();
+        assert!(error.contains("need a `high`"), "{error}");
+        let error = trigger(
+            r#"{ message = "note_on", channel = 0, note = 0x24, velocity = 0x40, match_type = "range", high = 0x20 }"#,
+        )
+        .unwrap_err();
+        assert!(error.contains("below the low end"), "{error}");
+        let error = trigger(
+            r#"{ message = "note_on", channel = 0, note = 0x24, velocity = 0x40, high = 0x50 }"#,
+        )
+        .unwrap_err();
+        assert!(error.contains("only goes with the range"), "{error}");
+    }
+
+    #[test]
+    fn sets_of_channels_and_notes() {
+        let trigger = trigger(
+            r#"{ message = "note_on", channel = [0, 9], note = { low = 0x24, high = 0x26 }, velocity = 0x7F }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            trigger.triggered_by(&note_on(9, 0x25, 0x7F)),
+            Some(TriggeredBy {
+                channel: Some(u4::new(9)),
+                number: Some(u7::new(0x25)),
+                captured: None,
+            })
+        );
+        assert!(trigger.triggered_by(&note_on(1, 0x25, 0x7F)).is_none());
+        assert!(trigger.triggered_by(&note_on(0, 0x27, 0x7F)).is_none());
+        // Registered under every combination
+        assert_eq!(trigger.exact_hash_keys_inner().len(), 6);
+    }
+
+    #[test]
+    fn led_events() {
+        let note =
+            trigger(r#"{ message = "note_off", channel = 1, note = [0x59, 0x5A], velocity = 0 }"#)
+                .unwrap();
+        assert_eq!(
+            note.led_events(true),
+            [note_on(1, 0x59, 0x7F), note_on(1, 0x5A, 0x7F)]
+        );
+        let knob =
+            trigger(r#"{ message = "controller", channel = 0, controller = 0x10, value = 0x7F }"#)
+                .unwrap();
+        assert_eq!(knob.led_events(false), [controller(0, 0x10, 0)]);
+        let start = trigger(r#"{ message = "start" }"#).unwrap();
+        assert!(start.led_events(true).is_empty());
+    }
+
+    fn sysex(data: &[u8]) -> LiveEvent<'_> {
+        LiveEvent::Common(SystemCommon::SysEx(u7::slice_from_int(data)))
+    }
+
+    fn pattern(pattern: &str) -> SysExPattern {
+        pattern.parse().unwrap()
+    }
+
+    fn pattern_matches(pattern: &SysExPattern, payload: &[u8]) -> Option<TriggeredBy> {
+        pattern.matches(u7::slice_from_int(payload))
+    }
+
+    #[test]
+    fn sysex_pattern_bytes() {
+        assert_eq!(
+            pattern("10 4? ?f vv *"),
+            SysExPattern {
+                bytes: vec![
+                    SysExByte {
+                        value: 0x10,
+                        mask: 0xFF,
+                        capture: false,
+                    },
+                    SysExByte {
+                        value: 0x40,
+                        mask: 0xF0,
+                        capture: false,
+                    },
+                    SysExByte {
+                        value: 0x0F,
+                        mask: 0x0F,
+                        capture: false,
+                    },
+                    SysExByte {
+                        value: 0,
+                        mask: 0,
+                        capture: true,
+                    },
+                ],
+                open_ended: true,
+            }
+        );
+        assert_eq!(
+            pattern(""),
+            SysExPattern {
+                bytes: Vec::new(),
+                open_ended: false,
+            }
+        );
+        assert_eq!(pattern("*"), SysExPattern::default());
+    }
+
+    #[test]
+    fn malformed_sysex_patterns() {
+        for (pattern, error) in [
+            ("10 * 20", "`*` can only go at the end"),
+            ("1", "isn't a byte"),
+            ("100", "isn't a byte"),
+            ("1g", "isn't a byte"),
+            ("80", "above 7F"),
+            ("?? 8?", "above 7F"),
+            ("vv vv vv vv vv", "4 bytes at most"),
+        ] {
+            let result = pattern.parse::<SysExPattern>();
+            assert!(
+                result
+                    .as_ref()
+                    .is_err_and(|message| message.contains(error)),
+                "{pattern:?} gave {result:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn sysex_wildcards_and_masks() {
+        let pattern = pattern("10 4? ?F");
+        assert!(pattern_matches(&pattern, &[0x10, 0x40, 0x0F]).is_some());
+        assert!(pattern_matches(&pattern, &[0x10, 0x4A, 0x7F]).is_some());
+        assert!(pattern_matches(&pattern, &[0x11, 0x40, 0x0F]).is_none());
+        assert!(pattern_matches(&pattern, &[0x10, 0x50, 0x0F]).is_none());
+        assert!(pattern_matches(&pattern, &[0x10, 0x40, 0x0E]).is_none());
+    }
+
+    #[test]
+    fn sysex_lengths() {
+        let exact = pattern("10 20");
+        assert!(pattern_matches(&exact, &[0x10]).is_none());
+        assert!(pattern_matches(&exact, &[0x10, 0x20]).is_some());
+        assert!(pattern_matches(&exact, &[0x10, 0x20, 0x30]).is_none());
+        let open_ended = pattern("10 20 *");
+        assert!(pattern_matches(&open_ended, &[0x10]).is_none());
+        assert!(pattern_matches(&open_ended, &[0x10, 0x20]).is_some());
+        assert!(pattern_matches(&open_ended, &[0x10, 0x20, 0x30]).is_some());
+    }
+
+    #[test]
+    fn sysex_captures() {
+        let captured = |pattern: &SysExPattern, payload: &[u8]| {
+            pattern_matches(pattern, payload).unwrap().captured
+        };
+        assert_eq!(captured(&pattern("10 20"), &[0x10, 0x20]), None);
+        let one = pattern("10 vv");
+        assert_eq!(
+            captured(&one, &[0x10, 0x00]),
+            Some(Captured { raw: 0, value: 0.0 })
+        );
+        assert_eq!(
+            captured(&one, &[0x10, 0x7F]),
+            Some(Captured {
+                raw: 0x7F,
+                value: 1.0
+            })
+        );
+        // Packed 7 bits per byte, most significant first
+        let two = pattern("vv 10 vv");
+        assert_eq!(
+            captured(&two, &[0x7F, 0x10, 0x7F]),
+            Some(Captured {
+                raw: 0x3FFF,
+                value: 1.0
+            })
+        );
+        let half = captured(&two, &[0x40, 0x10, 0x01]).unwrap();
+        assert_eq!(half.raw, 8193);
+        assert!((half.value - 8193.0 / 16383.0).abs() < 1e-6, "{half:?}");
+    }
+
+    #[test]
+    fn sysex_manufacturer_ids() {
+        let yamaha =
+            trigger(r#"{ message = "sysex", manufacturer = 0x43, pattern = "10 vv" }"#).unwrap();
+        assert!(yamaha.triggered_by(&sysex(&[0x43, 0x10, 0x7F])).is_some());
+        assert!(yamaha.triggered_by(&sysex(&[0x42, 0x10, 0x7F])).is_none());
+        assert_eq!(yamaha.threshold_hash_keys_inner(), [sysex(&[0x43])]);
+
+        let behringer = trigger(
+            r#"{ message = "sysex", manufacturer = [0x00, 0x20, 0x32], pattern = "10 vv" }"#,
+        )
+        .unwrap();
+        assert!(behringer
+            .triggered_by(&sysex(&[0x00, 0x20, 0x32, 0x10, 0x7F]))
+            .is_some());
+        // The same bytes after a different 3-byte ID, or too short to hold one
+        assert!(behringer
+            .triggered_by(&sysex(&[0x00, 0x20, 0x33, 0x10, 0x7F]))
+            .is_none());
+        assert!(behringer.triggered_by(&sysex(&[0x00, 0x20])).is_none());
+        assert!(behringer
+            .triggered_by(&sysex(&[0x32, 0x10, 0x7F]))
+            .is_none());
+
+        let anything = trigger(r#"{ message = "sysex", manufacturer = 0x7D }"#).unwrap();
+        assert!(anything.triggered_by(&sysex(&[0x7D])).is_some());
+        assert!(anything.triggered_by(&sysex(&[0x7D, 0x01, 0x02])).is_some());
+    }
+
+    #[test]
+    fn malformed_manufacturer_ids() {
+        for (manufacturer, error) in [
+            ("0", "starting with 0 are 3 bytes long"),
+            ("[0x43, 0x10, 0x10]", "3 bytes starting with 0"),
+            ("[0, 1]", "3 bytes starting with 0"),
+            ("0x80", "out of range"),
+            ("[0, 1, 0x80]", "out of range"),
+        ] {
+            let result = trigger(&format!(
+                r#"{{ message = "sysex", manufacturer = {manufacturer} }}"#
+            ));
+            assert!(
+                result
+                    .as_ref()
+                    .is_err_and(|message| message.contains(error)),
+                "{manufacturer} gave {result:?}"
+            );
+        }
+        let error =
+            trigger(r#"{ message = "sysex", manufacturer = 0x43, pattern = "80" }"#).unwrap_err();
+        assert!(error.contains("above 7F"), "{error}");
+    }
+}
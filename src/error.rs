@@ -1,23 +1,52 @@
+use std::path::PathBuf;
+
 use derive_more::From;
-use midir::MidiInput;
+use midir::{MidiInput, MidiOutput};
+
+use crate::audio::Target;
 
 pub type Result<T> = core::result::Result<T, Error>;
 
 #[derive(Debug, From)]
 pub enum Error {
     DeviceNotFound,
+    AudioTargetNotFound(Target),
+    /// `pactl` failed, with what it had to say about that
+    Pactl(String),
+    /// A message from the controller that isn't valid MIDI, or is cut short
+    MidiParse {
+        bytes: Vec<u8>,
+        error: midly::Error,
+    },
+    Config {
+        path: PathBuf,
+        line: usize,
+        column: usize,
+        message: String,
+    },
     // -- Externals
     #[from]
     Dotenv(dotenvy::Error),
     #[from]
+    Io(std::io::Error),
+    #[from]
+    Json(serde_json::Error),
+    #[from]
     MspcReceive(std::sync::mpsc::RecvError),
     #[from]
     MspcSend(std::sync::mpsc::SendError<()>),
     #[from]
     MidiConnect(midir::ConnectError<MidiInput>),
     #[from]
+    MidiConnectOutput(midir::ConnectError<MidiOutput>),
+    #[from]
     MidiInit(midir::InitError),
     #[from]
+    Notify(notify::Error),
+    #[from]
+    Utf16(std::string::FromUtf16Error),
+    #[cfg(windows)]
+    #[from]
     Windows(windows::core::Error),
 }
 
@@ -25,7 +54,37 @@ pub enum Error {
 
 impl core::fmt::Display for Error {
     fn fmt(&self, fmt: &mut core::fmt::Formatter) -> core::result::Result<(), core::fmt::Error> {
-        write!(fmt, "{self:?}")
+        match self {
+            Self::DeviceNotFound => write!(fmt, "MIDI device not found"),
+            Self::AudioTargetNotFound(Target::Device(id)) => {
+                write!(fmt, "Audio device not found: {id}")
+            }
+            Self::AudioTargetNotFound(Target::Session(id)) => {
+                write!(fmt, "Audio session not found: {id}")
+            }
+            Self::Pactl(message) => write!(fmt, "{message}"),
+            Self::MidiParse { bytes, error } => write!(fmt, "{bytes:02X?}: {error}"),
+            // The way compilers put it, so that editors and terminals can
+            // jump to the spot
+            Self::Config {
+                path,
+                line,
+                column,
+                message,
+            } => write!(fmt, "{}:{line}:{column}: {message}", path.display()),
+            Self::Dotenv(error) => write!(fmt, "{error}"),
+            Self::Io(error) => write!(fmt, "{error}"),
+            Self::Json(error) => write!(fmt, "{error}"),
+            Self::MspcReceive(error) => write!(fmt, "{error}"),
+            Self::MspcSend(error) => write!(fmt, "{error}"),
+            Self::MidiConnect(error) => write!(fmt, "{error}"),
+            Self::MidiConnectOutput(error) => write!(fmt, "{error}"),
+            Self::MidiInit(error) => write!(fmt, "{error}"),
+            Self::Notify(error) => write!(fmt, "{error}"),
+            Self::Utf16(error) => write!(fmt, "{error}"),
+            #[cfg(windows)]
+            Self::Windows(error) => write!(fmt, "{error}"),
+        }
     }
 }
 
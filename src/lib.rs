@@ -0,0 +1,8 @@
+pub mod audio;
+pub mod error;
+#[cfg(target_os = "linux")]
+pub mod pulse_audio;
+#[cfg(windows)]
+pub mod utils;
+#[cfg(windows)]
+pub mod windows_audio;
@@ -1,21 +1,30 @@
+mod actions;
+mod config;
+mod context;
 mod controls;
-mod error;
-use std::{collections::HashMap, ops::Deref, sync::Arc};
-
-use controls::{
-    trigger::{
-        live_event_without_value, TriggerMidiMessage, TriggerNoteOn, ValueMatchType,
-    },
-    Control,
-    TriggerConfig,
+mod mappings;
+use std::{
+    ops::Deref,
+    path::PathBuf,
+    sync::mpsc::{Receiver, RecvError, RecvTimeoutError},
+    time::Instant,
 };
+
+use config::Config;
+use context::Context;
 use error::{Error, Result};
-use log::debug;
+use log::{debug, error, info, warn};
+use mappings::Mappings;
+#[cfg(windows)]
+use midi_windows_controller::windows_audio;
+use midi_windows_controller::{
+    audio::{self, Audio},
+    error,
+};
 use midir::MidiInput;
-use midly::{io::IoWrap, live::LiveEvent, num::{u4, u7}};
+use midly::{io::IoWrap, live::LiveEvent};
 use smallvec::SmallVec;
 mod midi;
-mod windows_audio;
 
 #[derive(Clone, Debug, Eq, Hash, PartialEq)]
 struct MidiBytes(SmallVec<[u8; 3]>); // 3 bytes is the common size for midi messages
@@ -43,80 +52,153 @@ impl Deref for MidiBytes {
     }
 }
 
+enum Event {
+    Midi(u64, MidiBytes),
+    ConfigChanged,
+    /// With the process id of the new foreground window
+    #[cfg(windows)]
+    ForegroundChanged(u32),
+    AudioChanged,
+}
+
+/// Waits for the next event, or `deadline`, whichever comes first. `None`
+/// means the deadline passed.
+fn next_event(event_rx: &Receiver<Event>, deadline: Option<Instant>) -> Result<Option<Event>> {
+    // Without a deadline there's nothing to do until the next event
+    let Some(deadline) = deadline else {
+        return Ok(Some(event_rx.recv()?));
+    };
+    match event_rx.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+        Ok(event) => Ok(Some(event)),
+        Err(RecvTimeoutError::Timeout) => Ok(None),
+        Err(RecvTimeoutError::Disconnected) => Err(RecvError.into()),
+    }
+}
+
 fn main() -> Result<()> {
     dotenvy::dotenv()?;
     env_logger::init();
-    let (midi_input_tx, midi_input_rx) = std::sync::mpsc::channel();
-    let mut exact_midi_events = HashMap::new();
-    let mut threshold_midi_events = HashMap::new();
-    let button1 = Arc::new(controls::ControlType::Trigger(TriggerConfig {
-        command: TriggerMidiMessage::NoteOn(TriggerNoteOn {
-            channel: u4::from(0),
-            note: u7::from(0x59),
-            velocity: u7::from(0x7F),
-            match_type: ValueMatchType::Exact,
-        }),
-        _auto_indicate: false,
-    }));
-    let button2 = Arc::new(controls::ControlType::Trigger(TriggerConfig {
-        command: TriggerMidiMessage::NoteOn(TriggerNoteOn {
-            channel: u4::from(0),
-            note: u7::from(0x5A),
-            velocity: u7::from(0x0F),
-            match_type: ValueMatchType::ThresholdOrAbove,
-        }),
-        _auto_indicate: false,
-    }));
-    let button3 = Arc::new(controls::ControlType::Trigger(TriggerConfig {
-        command: TriggerMidiMessage::NoteOn(TriggerNoteOn {
-            channel: u4::from(0),
-            note: u7::from(0x5B),
-            velocity: u7::from(0x7F),
-            match_type: ValueMatchType::ThresholdOrBelow,
-        }),
-        _auto_indicate: false,
-    }));
-    let controls = vec![button1, button2, button3];
-    for control in controls {
-        if let Some(exact_key) = control.exact_hash_key() {
-            exact_midi_events.insert(exact_key, vec![control.clone()]);
-        }
-        if let Some(threshold_key) = control.threshold_hash_key() {
-            threshold_midi_events.insert(threshold_key, vec![control.clone()]);
-        }
-    }
+    let (event_tx, event_rx) = std::sync::mpsc::channel();
+    let config_path = std::env::args_os()
+        .nth(1)
+        .map_or_else(|| PathBuf::from("mappings.toml"), PathBuf::from);
+    let config = Config::load(&config_path)?;
+    let input_port = config.input.port;
+    let output_port = config.output.map(|output| output.port);
+    let audio_event_tx = event_tx.clone();
+    let mut context = Context::new(
+        output_port
+            .as_deref()
+            .map(midi::connect_output)
+            .transpose()?,
+        Audio::new(audio::system_backend(move || {
+            let _ = audio_event_tx.send(Event::AudioChanged);
+        })?),
+    );
+    let mut mappings = Mappings::new(
+        config.controls,
+        config.layers,
+        config.input.treat_zero_velocity_as_note_off,
+    );
+    mappings.indicate(&mut context);
+    let config_event_tx = event_tx.clone();
+    let _watcher = config::watch(&config_path, move || {
+        // If the main thread is gone there's nobody left to reload for
+        let _ = config_event_tx.send(Event::ConfigChanged);
+    })?;
+    #[cfg(windows)]
+    let foreground_event_tx = event_tx.clone();
+    #[cfg(windows)]
+    windows_audio::watch_foreground(move |pid| {
+        let _ = foreground_event_tx.send(Event::ForegroundChanged(pid));
+    })?;
     let midi_in = MidiInput::new("MIDI Windows Controller")?;
     let in_ports = midi_in.ports();
-    let in_port = in_ports.iter().find(|port| {
-        midi_in
-            .port_name(port)
-            .map_or(false, |name| name == "X-TOUCH MINI")
-    });
+    let in_port = in_ports
+        .iter()
+        .find(|port| midi_in.port_name(port).is_ok_and(|name| name == input_port));
     let in_port = in_port.ok_or(Error::DeviceNotFound)?;
     let _conn = midi_in.connect(
         in_port,
         "event-listener",
-        |_ts, message, midi_input_tx| {
+        |timestamp, message, event_tx| {
             debug!("Received midi message: {:?}", message);
             let message = MidiBytes::from_slice(message);
-            midi_input_tx
-                .send(message)
-                .expect("Failed to send midi event to processing thread");
+            // If the main thread is gone there's nobody left to handle it
+            let _ = event_tx.send(Event::Midi(timestamp, message));
         },
-        midi_input_tx,
+        event_tx,
     )?;
-    debug!("Maps: {:?}", exact_midi_events);
+    debug!("Maps: {:?}", mappings);
+    // A controller that sends garbage now and then is worth knowing about,
+    // but not worth stopping for
+    let mut parse_errors = 0_u64;
     loop {
-        let bytes: MidiBytes = midi_input_rx.recv()?;
-        debug!("Received midi event: {:?}", bytes);
-        let triggers = exact_midi_events.get(&bytes);
-        for trigger in triggers.into_iter().flatten() {
-            trigger.handle_midi_event(&bytes);
-        }
-        let event_without_value = live_event_without_value(&bytes);
-        let triggers = threshold_midi_events.get(&event_without_value);
-        for trigger in triggers.into_iter().flatten() {
-            trigger.handle_midi_event(&bytes);
+        let event = next_event(&event_rx, mappings.next_deadline())?;
+        // Before the event, which might well be the release that a long
+        // press is waiting for
+        mappings.handle_deadlines(Instant::now(), &mut context);
+        let Some(event) = event else {
+            continue;
+        };
+        match event {
+            Event::Midi(timestamp, bytes) => {
+                debug!("Received midi event: {:?}", bytes);
+                if let Err(error) = mappings.handle_midi_event(&bytes, timestamp, &mut context) {
+                    parse_errors += 1;
+                    warn!("Ignoring midi message ({parse_errors} so far): {error}");
+                }
+            }
+            Event::ConfigChanged => {
+                // Editors can produce several events for a single save, some
+                // of them while the file is only partially written. Those
+                // just fail to load, and the next event picks up the rest.
+                match Config::load(&config_path) {
+                    Ok(config) => {
+                        if config.input.port != input_port {
+                            warn!(
+                                "Input port changed to {:?}, this needs a restart to take effect",
+                                config.input.port
+                            );
+                        }
+                        let new_output_port = config.output.map(|output| output.port);
+                        if new_output_port != output_port {
+                            warn!(
+                                "Output port changed to {new_output_port:?}, this needs a restart to take effect"
+                            );
+                        }
+                        mappings = Mappings::new(
+                            config.controls,
+                            config.layers,
+                            config.input.treat_zero_velocity_as_note_off,
+                        );
+                        mappings.indicate(&mut context);
+                        info!("Reloaded mappings from {}", config_path.display());
+                        debug!("Maps: {:?}", mappings);
+                    }
+                    Err(error) => {
+                        error!("Keeping previous mappings, failed to reload: {error}");
+                    }
+                }
+            }
+            #[cfg(windows)]
+            Event::ForegroundChanged(pid) => {
+                debug!("Foreground window changed to pid {pid}");
+                context.audio.foreground_changed(pid);
+                // Whatever follows the foreground window has a new target
+                mappings.indicate(&mut context);
+            }
+            Event::AudioChanged => match context.audio.update() {
+                Ok(changes) => {
+                    for change in &changes {
+                        info!("{change}");
+                    }
+                    if !changes.is_empty() {
+                        mappings.indicate(&mut context);
+                    }
+                }
+                Err(error) => warn!("Failed to keep track of audio devices: {error}"),
+            },
         }
     }
 }
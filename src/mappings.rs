@@ -0,0 +1,1383 @@
+use std::{cell::RefCell, collections::HashMap, sync::Arc, time::Instant};
+
+use log::{info, warn};
+use midly::{live::LiveEvent, MidiMessage};
+
+use crate::{
+    config::LayerConfig,
+    context::Context,
+    controls::{button::ButtonSource, trigger::live_event_without_value, Control, ControlType},
+    error::{Error, Result},
+    MidiBytes,
+};
+
+/// Lookup tables from incoming MIDI messages to the controls interested in
+/// them. These are built in one go from a config, so swapping the whole thing
+/// out is all it takes to apply a new config.
+#[derive(Debug, Default)]
+pub(crate) struct Mappings {
+    controls: Bindings,
+    /// In config order, later ones take precedence
+    layers: Vec<Layer>,
+    /// See `InputConfig::treat_zero_velocity_as_note_off`
+    treat_zero_velocity_as_note_off: bool,
+}
+
+/// One set of controls with its lookup tables, either the main one or that of
+/// a layer.
+#[derive(Debug, Default)]
+struct Bindings {
+    controls: Vec<Arc<ControlType>>,
+    /// Indices into `controls`, in config order
+    exact_midi_events: HashMap<MidiBytes, Vec<usize>>,
+    /// Indices into `controls`, in config order
+    threshold_midi_events: HashMap<MidiBytes, Vec<usize>>,
+    /// Indices into `controls` of the members of each radio group
+    radio_groups: HashMap<String, Vec<usize>>,
+    /// In the order the messages came in
+    held_back: RefCell<Vec<HeldBack>>,
+}
+
+/// A message that the controls at `indices` only get at `until`, as the one
+/// at `holder` might still capture it, see `Control::holds_back`
+#[derive(Debug)]
+struct HeldBack {
+    bytes: MidiBytes,
+    timestamp: u64,
+    until: Instant,
+    holder: usize,
+    indices: Vec<usize>,
+    /// Whether they get it with `handle_captured_event`
+    captured: bool,
+}
+
+impl HeldBack {
+    fn deliver(&self, controls: &[Arc<ControlType>], captured: bool, context: &mut Context) {
+        // It was parsed before it was held back
+        let event = LiveEvent::parse(&self.bytes).unwrap();
+        for &index in &self.indices {
+            if captured {
+                controls[index].handle_captured_event(&event, self.timestamp, context);
+            } else {
+                controls[index].handle_midi_event(&event, self.timestamp, context);
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Layer {
+    name: String,
+    button: ButtonSource,
+    toggle: bool,
+    bindings: Bindings,
+}
+
+impl Bindings {
+    fn new(controls: Vec<ControlType>) -> Self {
+        let mut bindings = Self::default();
+        for (index, control) in controls.into_iter().map(Arc::new).enumerate() {
+            // Controls that cover several channels or notes are registered
+            // under each of them, so looking them up stays a single hash
+            for exact_key in control.exact_hash_keys() {
+                bindings
+                    .exact_midi_events
+                    .entry(exact_key)
+                    .or_default()
+                    .push(index);
+            }
+            for threshold_key in control.threshold_hash_keys() {
+                bindings
+                    .threshold_midi_events
+                    .entry(threshold_key)
+                    .or_default()
+                    .push(index);
+            }
+            if let Some(group) = control.radio_group() {
+                bindings
+                    .radio_groups
+                    .entry(group.to_owned())
+                    .or_default()
+                    .push(index);
+            }
+            bindings.controls.push(control);
+        }
+        bindings
+    }
+
+    /// Several controls for the same message is fine, e.g. to both mute
+    /// something and light an LED, but it can also be a copy and paste
+    /// mistake, so point them out.
+    ///
+    /// Controls that cover whole ranges of notes or channels overlap on many
+    /// messages at once, so those are reported together.
+    fn warn_about_overlaps(&self, layer: Option<&str>) {
+        let mut grouped: HashMap<Vec<usize>, Vec<&MidiBytes>> = HashMap::new();
+        for (key, indices) in self.overlaps() {
+            grouped.entry(indices).or_default().push(key);
+        }
+        let mut grouped: Vec<_> = grouped.into_iter().collect();
+        grouped.sort_by(|(a, _), (b, _)| a.cmp(b));
+        let in_layer = layer.map(|name| format!(" of layer {name}"));
+        for (indices, mut keys) in grouped {
+            let numbers = indices
+                .iter()
+                .map(|index| format!("#{}", index + 1))
+                .collect::<Vec<_>>()
+                .join(", ");
+            keys.sort_by(|a, b| a[..].cmp(&b[..]));
+            let messages = keys
+                .iter()
+                .map(|key| format!("{:02X?}", &key[..]))
+                .collect::<Vec<_>>()
+                .join(", ");
+            warn!(
+                "Controls {numbers}{} all handle {messages}, they run in that order",
+                in_layer.as_deref().unwrap_or_default()
+            );
+        }
+    }
+
+    /// The controls interested in a message, in config order
+    fn indices(&self, bytes: &MidiBytes, event_without_value: &MidiBytes) -> Vec<usize> {
+        // Both kinds of controls can be interested in the same message, and
+        // they should run in config order regardless
+        let mut indices: Vec<usize> = self
+            .exact_midi_events
+            .get(bytes)
+            .into_iter()
+            .chain(self.threshold_midi_events.get(event_without_value))
+            .flatten()
+            .copied()
+            .collect();
+        indices.sort_unstable();
+        indices.dedup();
+        indices
+    }
+
+    /// When one of the controls captures the message, e.g. a chord it
+    /// completes, the others only get to keep track of it. When one holds it
+    /// back, the others get it later, unless it gets captured by then.
+    fn dispatch(
+        &self,
+        indices: &[usize],
+        bytes: &MidiBytes,
+        event: &LiveEvent,
+        timestamp: u64,
+        context: &mut Context,
+    ) {
+        let captures: Vec<bool> = indices
+            .iter()
+            .map(|&index| self.controls[index].captures(event, timestamp))
+            .collect();
+        let captured = captures.contains(&true);
+        for (&index, &captures) in indices.iter().zip(&captures) {
+            if captures {
+                // What it held back turned out to be part of what it captures
+                self.drop_held_back(index, context);
+            }
+        }
+        let holders: Vec<(usize, Instant)> = if captured {
+            Vec::new()
+        } else {
+            let now = Instant::now();
+            indices
+                .iter()
+                .filter_map(|&index| Some((index, now + self.controls[index].holds_back(event)?)))
+                .collect()
+        };
+        let queue = holders.first().copied().or_else(|| self.queue(indices));
+        let mut held_back = Vec::new();
+        for (&index, captures) in indices.iter().zip(captures) {
+            let control = &self.controls[index];
+            let holder = captures
+                || self.holding(index)
+                || holders.iter().any(|(holder, _)| *holder == index);
+            if queue.is_some() && !holder {
+                held_back.push(index);
+            } else if captured && !captures {
+                control.handle_captured_event(event, timestamp, context);
+            } else {
+                control.handle_midi_event(event, timestamp, context);
+            }
+        }
+        self.hold_back(queue, bytes, timestamp, held_back, captured);
+    }
+
+    /// For a message that went to another layer
+    fn keep_track(
+        &self,
+        indices: &[usize],
+        bytes: &MidiBytes,
+        event: &LiveEvent,
+        timestamp: u64,
+        context: &mut Context,
+    ) {
+        let queue = self.queue(indices);
+        let mut held_back = Vec::new();
+        for &index in indices {
+            if queue.is_some() && !self.holding(index) {
+                held_back.push(index);
+            } else {
+                self.controls[index].handle_captured_event(event, timestamp, context);
+            }
+        }
+        self.hold_back(queue, bytes, timestamp, held_back, true);
+    }
+
+    /// Controls that hold messages back keep getting everything right away,
+    /// or they couldn't capture what comes next
+    fn holding(&self, index: usize) -> bool {
+        self.held_back
+            .borrow()
+            .iter()
+            .any(|held| held.holder == index)
+    }
+
+    fn hold_back(
+        &self,
+        queue: Option<(usize, Instant)>,
+        bytes: &MidiBytes,
+        timestamp: u64,
+        indices: Vec<usize>,
+        captured: bool,
+    ) {
+        let Some((holder, until)) = queue.filter(|_| !indices.is_empty()) else {
+            return;
+        };
+        self.held_back.borrow_mut().push(HeldBack {
+            bytes: bytes.clone(),
+            timestamp,
+            until,
+            holder,
+            indices,
+            captured,
+        });
+    }
+
+    /// Where a message for the controls at `indices` has to wait, so that it
+    /// doesn't get to them before one that is held back already
+    fn queue(&self, indices: &[usize]) -> Option<(usize, Instant)> {
+        let held_back = self.held_back.borrow();
+        let waiting = held_back
+            .iter()
+            .filter(|held| held.indices.iter().any(|index| indices.contains(index)));
+        let holder = waiting.clone().next_back()?.holder;
+        Some((holder, waiting.map(|held| held.until).max()?))
+    }
+
+    /// The controls it was held back from only get to keep track of it
+    fn drop_held_back(&self, holder: usize, context: &mut Context) {
+        let (dropped, kept): (Vec<_>, Vec<_>) = self
+            .held_back
+            .take()
+            .into_iter()
+            .partition(|held| held.holder == holder);
+        *self.held_back.borrow_mut() = kept;
+        for held in dropped {
+            held.deliver(&self.controls, true, context);
+        }
+    }
+
+    fn next_release(&self) -> Option<Instant> {
+        self.held_back.borrow().iter().map(|held| held.until).min()
+    }
+
+    fn release_held_back(&self, now: Instant, context: &mut Context) {
+        let (due, later): (Vec<_>, Vec<_>) = self
+            .held_back
+            .take()
+            .into_iter()
+            .partition(|held| held.until <= now);
+        *self.held_back.borrow_mut() = later;
+        for held in due {
+            held.deliver(&self.controls, held.captured, context);
+        }
+    }
+
+    /// The messages that more than one control handles, with those controls
+    /// in config order. An exact message also goes to the threshold controls
+    /// for the same message without its value.
+    fn overlaps(&self) -> Vec<(&MidiBytes, Vec<usize>)> {
+        let mut overlaps = Vec::new();
+        for (key, exact) in &self.exact_midi_events {
+            // The keys came from valid events in the first place
+            let event = LiveEvent::parse(key).unwrap();
+            let threshold = self
+                .threshold_midi_events
+                .get(&live_event_without_value(&event));
+            let mut indices: Vec<usize> = exact
+                .iter()
+                .chain(threshold.into_iter().flatten())
+                .copied()
+                .collect();
+            indices.sort_unstable();
+            indices.dedup();
+            if indices.len() > 1 {
+                overlaps.push((key, indices));
+            }
+        }
+        for (key, indices) in &self.threshold_midi_events {
+            // Unless they already came up along with an exact message
+            let covered = overlaps
+                .iter()
+                .any(|(_, reported)| indices.iter().all(|index| reported.contains(index)));
+            if indices.len() > 1 && !covered {
+                overlaps.push((key, indices.clone()));
+            }
+        }
+        overlaps.sort_by_key(|(_, indices)| indices[0]);
+        overlaps
+    }
+
+    fn indicate(&self, context: &mut Context) {
+        for control in &self.controls {
+            control.indicate(context);
+        }
+    }
+
+    fn indicate_group(&self, group: &str, context: &mut Context) {
+        for &index in self.radio_groups.get(group).into_iter().flatten() {
+            self.controls[index].indicate(context);
+        }
+    }
+}
+
+impl Mappings {
+    pub(crate) fn new(
+        controls: Vec<ControlType>,
+        layers: Vec<LayerConfig>,
+        treat_zero_velocity_as_note_off: bool,
+    ) -> Self {
+        let controls = Bindings::new(controls);
+        controls.warn_about_overlaps(None);
+        let layers = layers
+            .into_iter()
+            .map(|layer| {
+                let bindings = Bindings::new(layer.controls);
+                bindings.warn_about_overlaps(Some(&layer.name));
+                Layer {
+                    name: layer.name,
+                    button: layer.button,
+                    toggle: layer.toggle,
+                    bindings,
+                }
+            })
+            .collect();
+        Self {
+            controls,
+            layers,
+            treat_zero_velocity_as_note_off,
+        }
+    }
+
+    /// The main controls followed by those of the active layers, so from the
+    /// bottom up
+    fn visible(&self, context: &Context) -> Vec<&Bindings> {
+        std::iter::once(&self.controls)
+            .chain(
+                self.layers
+                    .iter()
+                    .filter(|layer| context.layer_active(&layer.name))
+                    .map(|layer| &layer.bindings),
+            )
+            .collect()
+    }
+
+    /// Including the layers that aren't active
+    fn all_bindings(&self) -> impl Iterator<Item = &Bindings> {
+        std::iter::once(&self.controls).chain(self.layers.iter().map(|layer| &layer.bindings))
+    }
+
+    fn all_controls(&self) -> impl Iterator<Item = &Arc<ControlType>> {
+        self.all_bindings().flat_map(|bindings| &bindings.controls)
+    }
+
+    /// Bottom up, so that where an active layer shares LEDs with the controls
+    /// below it, the layer's feedback ends up being shown
+    pub(crate) fn indicate(&self, context: &mut Context) {
+        for bindings in self.visible(context) {
+            bindings.indicate(context);
+        }
+        for layer in &self.layers {
+            let event = layer.button.led_event(context.layer_active(&layer.name));
+            context.send(&event);
+        }
+    }
+
+    /// The earliest moment any control wants `handle_deadlines` to be called,
+    /// or a held back message is due
+    pub(crate) fn next_deadline(&self) -> Option<Instant> {
+        self.all_controls()
+            .filter_map(|control| control.deadline())
+            .chain(self.all_bindings().filter_map(Bindings::next_release))
+            .min()
+    }
+
+    /// Controls in layers that aren't active get theirs as well, e.g. to
+    /// finish a tap that started before the layer changed
+    pub(crate) fn handle_deadlines(&self, now: Instant, context: &mut Context) {
+        for bindings in self.all_bindings() {
+            bindings.release_held_back(now, context);
+        }
+        for control in self.all_controls() {
+            if control.deadline().is_some_and(|deadline| deadline <= now) {
+                control.handle_deadline(now, context);
+            }
+        }
+        self.indicate_changed_groups(context);
+    }
+
+    /// The member that got selected updated its own feedback already, but
+    /// the one it replaced doesn't know yet
+    fn indicate_changed_groups(&self, context: &mut Context) {
+        for group in context.take_changed_groups() {
+            for bindings in self.visible(context) {
+                bindings.indicate_group(&group, context);
+            }
+        }
+    }
+
+    /// Returns whether `event` came from one of the layer buttons
+    fn handle_layer_button(&self, event: &LiveEvent, context: &mut Context) -> bool {
+        let Some((layer, pressed)) = self
+            .layers
+            .iter()
+            .find_map(|layer| Some((layer, layer.button.pressed(event)?)))
+        else {
+            return false;
+        };
+        let active = match (layer.toggle, pressed) {
+            (false, pressed) => pressed,
+            (true, true) => !context.layer_active(&layer.name),
+            // Toggled layers don't care about releases
+            (true, false) => return true,
+        };
+        if context.set_layer_active(&layer.name, active) {
+            info!(
+                "Layer {} {}",
+                layer.name,
+                if active { "activated" } else { "deactivated" }
+            );
+            self.indicate(context);
+        }
+        true
+    }
+
+    pub(crate) fn handle_midi_event(
+        &self,
+        bytes: &MidiBytes,
+        timestamp: u64,
+        context: &mut Context,
+    ) -> Result<()> {
+        let mut event = LiveEvent::parse(bytes).map_err(|error| Error::MidiParse {
+            bytes: bytes.to_vec(),
+            error,
+        })?;
+        // Normalize before the lookups, so that note off controls don't need
+        // to know how the controller sends note off
+        let normalized;
+        let bytes = match event {
+            LiveEvent::Midi {
+                channel,
+                message: MidiMessage::NoteOn { key, vel },
+            } if vel == 0 && self.treat_zero_velocity_as_note_off => {
+                event = LiveEvent::Midi {
+                    channel,
+                    message: MidiMessage::NoteOff { key, vel },
+                };
+                normalized = MidiBytes::from(event);
+                &normalized
+            }
+            _ => bytes,
+        };
+        if self.handle_layer_button(&event, context) {
+            return Ok(());
+        }
+        let event_without_value = live_event_without_value(&event);
+        // The topmost active layer with any controls for the message gets it,
+        // the ones below don't
+        let visible = self.visible(context);
+        let handling = visible.iter().rev().find_map(|bindings| {
+            let indices = bindings.indices(bytes, &event_without_value);
+            (!indices.is_empty()).then_some((bindings, indices))
+        });
+        if let Some((bindings, indices)) = &handling {
+            bindings.dispatch(indices, bytes, &event, timestamp, context);
+        }
+        // The others still get to keep track of it, so that e.g. a chord
+        // doesn't miss the release of a key that went to a layer
+        for bindings in self.all_bindings() {
+            if handling
+                .as_ref()
+                .is_some_and(|(handling, _)| std::ptr::eq(**handling, bindings))
+            {
+                continue;
+            }
+            let indices = bindings.indices(bytes, &event_without_value);
+            if !indices.is_empty() {
+                bindings.keep_track(&indices, bytes, &event, timestamp, context);
+            }
+        }
+        self.indicate_changed_groups(context);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc, time::Duration};
+
+    use midi_windows_controller::audio::{
+        mock::MockBackend, Audio, AudioBackend, Change, Flow, Role, Target,
+    };
+
+    use super::*;
+    use crate::config::Config;
+
+    /// Mappings loaded from a config, running against a mock audio stack
+    struct Harness {
+        mappings: Mappings,
+        context: Context,
+        audio: Rc<RefCell<MockBackend>>,
+        timestamp: u64,
+    }
+
+    impl Harness {
+        /// `controls` is the config without the `[input]` section
+        fn new(controls: &str) -> Self {
+            Self::with_input("", controls)
+        }
+
+        /// `input` goes in the `[input]` section, after the port
+        fn with_input(input: &str, controls: &str) -> Self {
+            let audio = Rc::new(RefCell::new(MockBackend::default()));
+            let context = Context::new(None, Audio::new(Box::new(audio.clone())));
+            Self {
+                mappings: mappings(input, controls),
+                context,
+                audio,
+                timestamp: 0,
+            }
+        }
+
+        /// What a reload does, and what happens at startup
+        fn indicate(&mut self) {
+            self.context.sent.clear();
+            self.mappings.indicate(&mut self.context);
+        }
+
+        /// Replaces the mappings, keeping the context like a reload does
+        fn reload(&mut self, controls: &str) {
+            self.mappings = mappings("", controls);
+            self.indicate();
+        }
+
+        /// A millisecond after the previous message
+        fn send(&mut self, bytes: &[u8]) {
+            self.send_after(1, bytes);
+        }
+
+        fn send_after(&mut self, ms: u64, bytes: &[u8]) {
+            self.timestamp += ms * 1000;
+            self.mappings
+                .handle_midi_event(
+                    &MidiBytes::from_slice(bytes),
+                    self.timestamp,
+                    &mut self.context,
+                )
+                .unwrap();
+        }
+
+        /// What the event loop does once `ms` have passed without messages
+        fn wait(&mut self, ms: u64) {
+            self.timestamp += ms * 1000;
+            self.mappings.handle_deadlines(
+                Instant::now() + Duration::from_millis(ms),
+                &mut self.context,
+            );
+        }
+
+        fn volume(&self, target: &Target) -> f32 {
+            self.context.audio.backend().volume(target).unwrap()
+        }
+
+        fn muted(&self, target: &Target) -> bool {
+            self.context.audio.backend().muted(target).unwrap()
+        }
+
+        fn default_device(&self) -> Option<String> {
+            self.context
+                .audio
+                .backend()
+                .default_device(Flow::Render, Role::Console)
+                .map(|device| device.id)
+        }
+    }
+
+    fn mappings(input: &str, controls: &str) -> Mappings {
+        let config: Config =
+            toml::from_str(&format!("[input]\nport = \"test\"\n{input}\n{controls}")).unwrap();
+        Mappings::new(
+            config.controls,
+            config.layers,
+            config.input.treat_zero_velocity_as_note_off,
+        )
+    }
+
+    fn session(id: &str) -> Target {
+        Target::Session(id.to_string())
+    }
+
+    fn device(id: &str) -> Target {
+        Target::Device(id.to_string())
+    }
+
+    fn assert_volume(actual: f32, expected: f32) {
+        assert!(
+            (actual - expected).abs() < 1e-4,
+            "volume is {actual} instead of {expected}"
+        );
+    }
+
+    #[test]
+    fn fader_sets_session_volume() {
+        let mut harness = Harness::new(
+            r#"
+            [[controls]]
+            type = "absolute_value"
+            command = { message = "controller", channel = 0, controller = 7 }
+            action = { name = "session_volume", process = "spotify*" }
+            "#,
+        );
+        {
+            let mut audio = harness.audio.borrow_mut();
+            audio.add_device("speakers", "Speakers", Flow::Render);
+            audio
+                .add_session("speakers", "a", Some("spotify"), 1)
+                .unwrap();
+            audio
+                .add_session("speakers", "b", Some("Discord"), 2)
+                .unwrap();
+        }
+        harness.send(&[0xB0, 7, 0x00]);
+        assert_volume(harness.volume(&session("a")), 0.0);
+        harness.send(&[0xB0, 7, 0x7F]);
+        assert_volume(harness.volume(&session("a")), 1.0);
+        // Another controller or channel doesn't move it
+        harness.send(&[0xB0, 8, 0x00]);
+        harness.send(&[0xB1, 7, 0x00]);
+        assert_volume(harness.volume(&session("a")), 1.0);
+        assert_volume(harness.volume(&session("b")), 1.0);
+    }
+
+    #[test]
+    fn button_toggles_session_mute() {
+        let mut harness = Harness::new(
+            r#"
+            [[controls]]
+            type = "trigger"
+            command = { message = "note_on", channel = 0, note = 0x59, velocity = 0x7F }
+            action = { name = "session_volume", process = "Discord*" }
+            "#,
+        );
+        {
+            let mut audio = harness.audio.borrow_mut();
+            audio.add_device("speakers", "Speakers", Flow::Render);
+            audio
+                .add_session("speakers", "a", Some("Discord"), 1)
+                .unwrap();
+            audio
+                .add_session("speakers", "b", Some("Discord"), 2)
+                .unwrap();
+            audio.mute_externally("b", true).unwrap();
+        }
+        // One of them was unmuted, so both get muted
+        harness.send(&[0x90, 0x59, 0x7F]);
+        assert!(harness.muted(&session("a")));
+        assert!(harness.muted(&session("b")));
+        // Releasing does nothing
+        harness.send(&[0x90, 0x59, 0x00]);
+        assert!(harness.muted(&session("a")));
+        harness.send(&[0x90, 0x59, 0x7F]);
+        assert!(!harness.muted(&session("a")));
+        assert!(!harness.muted(&session("b")));
+    }
+
+    #[test]
+    fn encoder_nudges_default_endpoint() {
+        let mut harness = Harness::new(
+            r#"
+            [[controls]]
+            type = "relative_value"
+            encoding = "sign_magnitude"
+            command = { message = "controller", channel = 0, controller = 0x10 }
+            action = { name = "endpoint_volume", step = 0.1 }
+            "#,
+        );
+        {
+            let mut audio = harness.audio.borrow_mut();
+            audio.add_device("speakers", "Speakers", Flow::Render);
+            audio.add_device("headset", "Headset", Flow::Render);
+            audio.set_default_device("headset", Role::Console).unwrap();
+        }
+        harness.send_after(1000, &[0xB0, 0x10, 0x43]);
+        assert_volume(harness.volume(&device("headset")), 0.7);
+        harness.send_after(1000, &[0xB0, 0x10, 0x01]);
+        assert_volume(harness.volume(&device("headset")), 0.8);
+        assert_volume(harness.volume(&device("speakers")), 1.0);
+    }
+
+    #[test]
+    fn encoder_ring_follows_the_volume() {
+        let mut harness = Harness::new(
+            r#"
+            [[controls]]
+            type = "relative_value"
+            encoding = "sign_magnitude"
+            command = { message = "controller", channel = 0, controller = 0x10 }
+            indicator = { command = { message = "controller", channel = 0, controller = 0x30 } }
+            action = { name = "endpoint_volume", step = 0.25 }
+            "#,
+        );
+        {
+            let mut audio = harness.audio.borrow_mut();
+            audio.add_device("speakers", "Speakers", Flow::Render);
+            audio.set_default_device("speakers", Role::Console).unwrap();
+            audio.set_volume(&device("speakers"), 0.5).unwrap();
+        }
+        let ring = |value| MidiBytes::from_slice(&[0xB0, 0x30, value]);
+        // Starts out where the volume is rather than at the bottom
+        harness.indicate();
+        assert_eq!(harness.context.sent, [ring(64)]);
+        harness.send_after(1000, &[0xB0, 0x10, 0x01]);
+        assert_eq!(harness.context.sent.last(), Some(&ring(95)));
+        // Changed somewhere else, then picked up on reload
+        harness
+            .audio
+            .borrow_mut()
+            .set_volume(&device("speakers"), 0.0)
+            .unwrap();
+        harness.indicate();
+        assert_eq!(harness.context.sent, [ring(0)]);
+        harness.send_after(1000, &[0xB0, 0x10, 0x01]);
+        assert_eq!(harness.context.sent.last(), Some(&ring(32)));
+    }
+
+    #[test]
+    fn indicator_shows_the_volume() {
+        let mut harness = Harness::new(
+            r#"
+            [[controls]]
+            type = "indicator"
+            value = 1.0
+            command = { message = "controller", channel = 0, controller = 0x31 }
+            action = { name = "session_volume", process = "spotify*" }
+            "#,
+        );
+        let meter = |value| MidiBytes::from_slice(&[0xB0, 0x31, value]);
+        // Without a session to show, it falls back to its value
+        harness.indicate();
+        assert_eq!(harness.context.sent, [meter(0x7F)]);
+        {
+            let mut audio = harness.audio.borrow_mut();
+            audio.add_device("speakers", "Speakers", Flow::Render);
+            audio
+                .add_session("speakers", "a", Some("spotify"), 1)
+                .unwrap();
+            audio.set_volume(&session("a"), 0.25).unwrap();
+        }
+        harness.indicate();
+        assert_eq!(harness.context.sent, [meter(32)]);
+    }
+
+    #[test]
+    fn default_device_follows_added_and_removed_devices() {
+        let mut harness = Harness::new(
+            r#"
+            [[controls]]
+            type = "trigger"
+            command = { message = "note_on", channel = 0, note = 0x5C, velocity = 0x7F }
+            action = { name = "default_device", devices = ["Headset*", "Speakers*"] }
+            "#,
+        );
+        harness
+            .audio
+            .borrow_mut()
+            .add_device("speakers", "Speakers", Flow::Render);
+        harness.send(&[0x90, 0x5C, 0x7F]);
+        assert_eq!(harness.default_device().as_deref(), Some("speakers"));
+
+        harness
+            .audio
+            .borrow_mut()
+            .add_device("headset", "Headset", Flow::Render);
+        harness.send(&[0x90, 0x5C, 0x7F]);
+        assert_eq!(harness.default_device().as_deref(), Some("headset"));
+        harness.send(&[0x90, 0x5C, 0x7F]);
+        assert_eq!(harness.default_device().as_deref(), Some("speakers"));
+        harness.send(&[0x90, 0x5C, 0x7F]);
+        assert_eq!(harness.default_device().as_deref(), Some("headset"));
+
+        harness.audio.borrow_mut().remove_device("headset").unwrap();
+        assert_eq!(harness.default_device(), None);
+        harness.send(&[0x90, 0x5C, 0x7F]);
+        assert_eq!(harness.default_device().as_deref(), Some("speakers"));
+        let changes = harness.context.audio.update().unwrap();
+        assert!(changes.contains(&Change::DeviceRemoved("Headset".to_string())));
+    }
+
+    #[test]
+    fn sessions_created_later_are_picked_up() {
+        let mut harness = Harness::new(
+            r#"
+            [[controls]]
+            type = "absolute_value"
+            command = { message = "controller", channel = 0, controller = 7 }
+            action = { name = "session_volume", process = "spotify*" }
+            "#,
+        );
+        harness
+            .audio
+            .borrow_mut()
+            .add_device("speakers", "Speakers", Flow::Render);
+        // Nothing to control yet, which isn't an error
+        harness.send(&[0xB0, 7, 0x00]);
+
+        harness
+            .audio
+            .borrow_mut()
+            .add_session("speakers", "a", Some("spotify"), 1)
+            .unwrap();
+        let changes = harness.context.audio.update().unwrap();
+        assert!(changes.contains(&Change::SessionCreated {
+            device: "Speakers".to_string(),
+            session: "spotify".to_string(),
+        }));
+        // A session starts out at full volume, and follows the fader from the
+        // next message on
+        assert_volume(harness.volume(&session("a")), 1.0);
+        harness.send(&[0xB0, 7, 0x00]);
+        assert_volume(harness.volume(&session("a")), 0.0);
+
+        harness.audio.borrow_mut().remove_session("a").unwrap();
+        harness.send(&[0xB0, 7, 0x7F]);
+        assert!(harness.context.audio.backend().sessions().is_empty());
+    }
+
+    #[test]
+    fn controls_run_in_config_order() {
+        let fader = r#"
+            [[controls]]
+            type = "absolute_value"
+            command = { message = "controller", channel = 0, controller = 7 }
+            action = { name = "session_volume", process = "spotify*" }
+            "#;
+        let inverted = format!("{fader}invert = true\n");
+        for (controls, expected) in [
+            (format!("{fader}{inverted}"), 0.0),
+            (format!("{inverted}{fader}"), 1.0),
+        ] {
+            let mut harness = Harness::new(&controls);
+            {
+                let mut audio = harness.audio.borrow_mut();
+                audio.add_device("speakers", "Speakers", Flow::Render);
+                audio
+                    .add_session("speakers", "a", Some("spotify"), 1)
+                    .unwrap();
+            }
+            // The last one to run wins
+            harness.send(&[0xB0, 7, 0x7F]);
+            assert_volume(harness.volume(&session("a")), expected);
+        }
+    }
+
+    #[test]
+    fn exact_and_threshold_controls_both_run() {
+        let mut harness = Harness::new(
+            r#"
+            [[controls]]
+            type = "trigger"
+            command = { message = "controller", channel = 0, controller = 7, value = 0x7F }
+            action = { name = "session_volume", process = "spotify*" }
+
+            [[controls]]
+            type = "absolute_value"
+            command = { message = "controller", channel = 0, controller = 7 }
+            action = { name = "session_volume", process = "spotify*" }
+            "#,
+        );
+        {
+            let mut audio = harness.audio.borrow_mut();
+            audio.add_device("speakers", "Speakers", Flow::Render);
+            audio
+                .add_session("speakers", "a", Some("spotify"), 1)
+                .unwrap();
+        }
+        harness.send(&[0xB0, 7, 0x00]);
+        assert_volume(harness.volume(&session("a")), 0.0);
+        assert!(!harness.muted(&session("a")));
+        harness.send(&[0xB0, 7, 0x7F]);
+        assert_volume(harness.volume(&session("a")), 1.0);
+        assert!(harness.muted(&session("a")));
+    }
+
+    #[test]
+    fn overlaps() {
+        let harness = Harness::new(
+            r#"
+            [[controls]]
+            type = "absolute_value"
+            command = { message = "controller", channel = 0, controller = 8 }
+
+            [[controls]]
+            type = "trigger"
+            command = { message = "note_on", channel = 0, note = 0x59, velocity = 0x7F }
+
+            [[controls]]
+            type = "absolute_value"
+            command = { message = "controller", channel = 0, controller = 7 }
+
+            [[controls]]
+            type = "trigger"
+            command = { message = "note_on", channel = 0, note = 0x59, velocity = 0x40, match_type = "threshold_or_above" }
+
+            [[controls]]
+            type = "absolute_value"
+            command = { message = "controller", channel = 0, controller = 7 }
+
+            [[controls]]
+            type = "trigger"
+            command = { message = "note_on", channel = 0, note = 0x5A, velocity = 0x7F }
+            "#,
+        );
+        let overlaps: Vec<_> = harness
+            .mappings
+            .controls
+            .overlaps()
+            .into_iter()
+            .map(|(key, indices)| (key.to_vec(), indices))
+            .collect();
+        // The exact note on overlaps with the threshold one for the same note
+        assert_eq!(
+            overlaps,
+            [
+                (vec![0x90, 0x59, 0x7F], vec![1, 3]),
+                (vec![0xB0, 7, 0], vec![2, 4]),
+            ]
+        );
+    }
+
+    #[test]
+    fn broken_messages_are_errors() {
+        let mut harness = Harness::new(
+            r#"
+            [[controls]]
+            type = "absolute_value"
+            command = { message = "controller", channel = 0, controller = 7 }
+            action = { name = "session_volume", process = "spotify*" }
+            "#,
+        );
+        {
+            let mut audio = harness.audio.borrow_mut();
+            audio.add_device("speakers", "Speakers", Flow::Render);
+            audio
+                .add_session("speakers", "a", Some("spotify"), 1)
+                .unwrap();
+        }
+        // Cut short, and a data byte without a status byte
+        for bytes in [&[0xB0, 7][..], &[0x07, 0x00]] {
+            let result = harness.mappings.handle_midi_event(
+                &MidiBytes::from_slice(bytes),
+                0,
+                &mut harness.context,
+            );
+            assert!(
+                matches!(result, Err(Error::MidiParse { .. })),
+                "{bytes:02X?}"
+            );
+        }
+        assert_volume(harness.volume(&session("a")), 1.0);
+        // And it carries on after them
+        harness.send(&[0xB0, 7, 0x00]);
+        assert_volume(harness.volume(&session("a")), 0.0);
+    }
+
+    #[test]
+    fn zero_velocity_note_on_is_note_off() {
+        const CONTROLS: &str = r#"
+            [[controls]]
+            type = "trigger"
+            command = { message = "note_off", channel = 0, note = 0x59, velocity = 0 }
+            action = { name = "session_volume", process = "Discord*" }
+            "#;
+        for (input, muted) in [
+            ("", true),
+            ("treat_zero_velocity_as_note_off = false", false),
+        ] {
+            let mut harness = Harness::with_input(input, CONTROLS);
+            {
+                let mut audio = harness.audio.borrow_mut();
+                audio.add_device("speakers", "Speakers", Flow::Render);
+                audio
+                    .add_session("speakers", "a", Some("Discord"), 1)
+                    .unwrap();
+            }
+            harness.send(&[0x90, 0x59, 0x00]);
+            assert_eq!(harness.muted(&session("a")), muted, "{input:?}");
+            // A real note off works either way
+            harness.send(&[0x80, 0x59, 0x00]);
+            assert_eq!(harness.muted(&session("a")), !muted, "{input:?}");
+        }
+    }
+
+    #[test]
+    fn latch_survives_reload() {
+        const CONTROLS: &str = r#"
+            [[controls]]
+            type = "trigger"
+            auto_indicate = true
+            switch = { type = "latch", name = "mic" }
+            command = { message = "note_on", channel = 0, note = 0x59, velocity = 0x7F }
+            action = { name = "session_volume", process = "Discord*" }
+            "#;
+        let mut harness = Harness::new(CONTROLS);
+        {
+            let mut audio = harness.audio.borrow_mut();
+            audio.add_device("speakers", "Speakers", Flow::Render);
+            audio
+                .add_session("speakers", "a", Some("Discord"), 1)
+                .unwrap();
+        }
+        let led = |on: bool| MidiBytes::from_slice(&[0x90, 0x59, if on { 0x7F } else { 0 }]);
+        harness.indicate();
+        assert_eq!(harness.context.sent, [led(false)]);
+        harness.send(&[0x90, 0x59, 0x7F]);
+        assert!(harness.muted(&session("a")));
+        assert_eq!(harness.context.sent.last(), Some(&led(true)));
+        harness.reload(CONTROLS);
+        assert_eq!(harness.context.sent, [led(true)]);
+        // Picks up where it was rather than starting over
+        harness.send(&[0x90, 0x59, 0x7F]);
+        assert!(!harness.muted(&session("a")));
+        assert_eq!(harness.context.sent.last(), Some(&led(false)));
+        // A latch under a different name starts out off
+        harness.send(&[0x90, 0x59, 0x7F]);
+        harness.reload(&CONTROLS.replace("\"mic\"", "\"speakers\""));
+        assert_eq!(harness.context.sent, [led(false)]);
+    }
+
+    #[test]
+    fn radio_group_survives_reload() {
+        const CONTROLS: &str = r#"
+            [[controls]]
+            type = "trigger"
+            auto_indicate = true
+            switch = { type = "radio", group = "encoder", member = "spotify" }
+            command = { message = "note_on", channel = 0, note = 0x5D, velocity = 0x7F }
+
+            [[controls]]
+            type = "trigger"
+            auto_indicate = true
+            switch = { type = "radio", group = "encoder", member = "discord" }
+            command = { message = "note_on", channel = 0, note = 0x5E, velocity = 0x7F }
+
+            [[controls]]
+            type = "relative_value"
+            encoding = "sign_magnitude"
+            command = { message = "controller", channel = 0, controller = 0x10 }
+            [controls.action]
+            name = "selection"
+            group = "encoder"
+            actions.spotify = { name = "session_volume", process = "spotify*", step = 0.1 }
+            actions.discord = { name = "session_volume", process = "Discord*", step = 0.1 }
+            "#;
+        let mut harness = Harness::new(CONTROLS);
+        {
+            let mut audio = harness.audio.borrow_mut();
+            audio.add_device("speakers", "Speakers", Flow::Render);
+            audio
+                .add_session("speakers", "a", Some("spotify"), 1)
+                .unwrap();
+            audio
+                .add_session("speakers", "b", Some("Discord"), 2)
+                .unwrap();
+        }
+        let led =
+            |note: u8, on: bool| MidiBytes::from_slice(&[0x90, note, if on { 0x7F } else { 0 }]);
+        // Nothing selected yet, so the encoder does nothing
+        harness.send_after(1000, &[0xB0, 0x10, 0x41]);
+        assert_volume(harness.volume(&session("a")), 1.0);
+        assert_volume(harness.volume(&session("b")), 1.0);
+
+        harness.context.sent.clear();
+        harness.send(&[0x90, 0x5D, 0x7F]);
+        assert!(harness
+            .context
+            .sent
+            .ends_with(&[led(0x5D, true), led(0x5E, false)]));
+        harness.send_after(1000, &[0xB0, 0x10, 0x41]);
+        assert_volume(harness.volume(&session("a")), 0.9);
+
+        harness.reload(CONTROLS);
+        assert_eq!(harness.context.sent, [led(0x5D, true), led(0x5E, false)]);
+        harness.send_after(1000, &[0xB0, 0x10, 0x41]);
+        assert_volume(harness.volume(&session("a")), 0.8);
+
+        // Selecting the other one turns the first one's LED off
+        harness.context.sent.clear();
+        harness.send(&[0x90, 0x5E, 0x7F]);
+        assert!(harness
+            .context
+            .sent
+            .ends_with(&[led(0x5D, false), led(0x5E, true)]));
+        harness.send_after(1000, &[0xB0, 0x10, 0x41]);
+        assert_volume(harness.volume(&session("a")), 0.8);
+        assert_volume(harness.volume(&session("b")), 0.9);
+    }
+
+    /// A mute button for Discord, and a fader for Spotify, with a layer that
+    /// turns the button into one for Spotify
+    const LAYERED: &str = r#"
+        [[controls]]
+        type = "trigger"
+        command = { message = "note_on", channel = 0, note = 0x59, velocity = 0x7F }
+        action = { name = "session_volume", process = "Discord*" }
+
+        [[controls]]
+        type = "absolute_value"
+        command = { message = "controller", channel = 0, controller = 7 }
+        action = { name = "session_volume", process = "spotify*" }
+
+        [[layers]]
+        name = "shift"
+        button = { message = "note", channel = 0, note = 0x54 }
+
+        [[layers.controls]]
+        type = "trigger"
+        command = { message = "note_on", channel = 0, note = 0x59, velocity = 0x7F }
+        action = { name = "session_volume", process = "spotify*" }
+        "#;
+
+    fn layered_config(toggle: bool) -> String {
+        LAYERED.replace(
+            "name = \"shift\"",
+            &format!("name = \"shift\"\ntoggle = {toggle}"),
+        )
+    }
+
+    fn layered(toggle: bool) -> Harness {
+        let harness = Harness::new(&layered_config(toggle));
+        {
+            let mut audio = harness.audio.borrow_mut();
+            audio.add_device("speakers", "Speakers", Flow::Render);
+            audio
+                .add_session("speakers", "discord", Some("Discord"), 1)
+                .unwrap();
+            audio
+                .add_session("speakers", "spotify", Some("spotify"), 2)
+                .unwrap();
+        }
+        harness
+    }
+
+    #[test]
+    fn shift_layer_takes_over_while_held() {
+        let mut harness = layered(false);
+        let led = |on: bool| MidiBytes::from_slice(&[0x90, 0x54, if on { 0x7F } else { 0 }]);
+        harness.indicate();
+        assert_eq!(harness.context.sent, [led(false)]);
+
+        harness.send(&[0x90, 0x54, 0x7F]);
+        assert_eq!(harness.context.sent.last(), Some(&led(true)));
+        harness.send(&[0x90, 0x59, 0x7F]);
+        assert!(harness.muted(&session("spotify")));
+        assert!(!harness.muted(&session("discord")));
+        // The layer has nothing for the fader, so it goes to the one below
+        harness.send(&[0xB0, 7, 0]);
+        assert_volume(harness.volume(&session("spotify")), 0.0);
+
+        harness.send(&[0x90, 0x54, 0x00]);
+        assert_eq!(harness.context.sent.last(), Some(&led(false)));
+        harness.send(&[0x90, 0x59, 0x7F]);
+        assert!(harness.muted(&session("discord")));
+        assert!(harness.muted(&session("spotify")));
+    }
+
+    #[test]
+    fn toggle_layer_survives_reload() {
+        let mut harness = layered(true);
+        let led = |on: bool| MidiBytes::from_slice(&[0x90, 0x54, if on { 0x7F } else { 0 }]);
+        harness.send(&[0x90, 0x54, 0x7F]);
+        // Releasing doesn't turn it off again
+        harness.send(&[0x90, 0x54, 0x00]);
+        assert_eq!(harness.context.sent.last(), Some(&led(true)));
+
+        harness.reload(&layered_config(true));
+        assert_eq!(harness.context.sent.last(), Some(&led(true)));
+        harness.send(&[0x90, 0x59, 0x7F]);
+        assert!(harness.muted(&session("spotify")));
+        assert!(!harness.muted(&session("discord")));
+
+        harness.send(&[0x90, 0x54, 0x7F]);
+        assert_eq!(harness.context.sent.last(), Some(&led(false)));
+        harness.send(&[0x90, 0x59, 0x7F]);
+        assert!(harness.muted(&session("discord")));
+    }
+
+    /// Triggers on notes 0x57 and 0x58 that flip latches `a` and `b`, and a
+    /// chord of the two that flips latch `chord`
+    const CHORD: &str = r#"
+        [[controls]]
+        type = "trigger"
+        switch = { type = "latch", name = "a" }
+        command = { message = "note_on", channel = 0, note = 0x57, velocity = 0x7F }
+
+        [[controls]]
+        type = "trigger"
+        switch = { type = "latch", name = "b" }
+        command = { message = "note_on", channel = 0, note = 0x58, velocity = 0x7F }
+
+        [[controls]]
+        type = "trigger"
+        switch = { type = "latch", name = "chord" }
+        [controls.command]
+        message = "chord"
+        within_ms = 50
+        keys = [
+            { message = "note", channel = 0, note = 0x57 },
+            { message = "note", channel = 0, note = 0x58 },
+        ]
+        "#;
+
+    impl Harness {
+        /// The states of latches `a`, `b` and `chord`
+        fn latches(&self) -> (bool, bool, bool) {
+            (
+                self.context.latched("a"),
+                self.context.latched("b"),
+                self.context.latched("chord"),
+            )
+        }
+    }
+
+    #[test]
+    fn chord_suppresses_its_keys() {
+        let mut harness = Harness::new(CHORD);
+        // Held back, in case it becomes part of the chord
+        harness.send(&[0x90, 0x57, 0x7F]);
+        assert_eq!(harness.latches(), (false, false, false));
+        // Which it does, so neither key does its own thing
+        harness.send_after(10, &[0x90, 0x58, 0x7F]);
+        assert_eq!(harness.latches(), (false, false, true));
+        assert_eq!(harness.mappings.next_deadline(), None);
+        harness.wait(1000);
+        assert_eq!(harness.latches(), (false, false, true));
+        // Nor while the chord is held, and letting go of one and pressing it
+        // again doesn't fire the chord again
+        harness.send(&[0x90, 0x58, 0x00]);
+        harness.send(&[0x90, 0x58, 0x7F]);
+        harness.send(&[0x90, 0x57, 0x00]);
+        harness.send(&[0x90, 0x57, 0x7F]);
+        harness.wait(1000);
+        assert_eq!(harness.latches(), (false, false, true));
+        harness.send(&[0x80, 0x57, 0x00]);
+        harness.send(&[0x80, 0x58, 0x00]);
+        // Once all keys are released, they are back to normal, only later
+        harness.send_after(1000, &[0x90, 0x58, 0x7F]);
+        assert_eq!(harness.latches(), (false, false, true));
+        harness.wait(50);
+        assert_eq!(harness.latches(), (false, true, true));
+    }
+
+    #[test]
+    fn chord_window() {
+        let mut harness = Harness::new(CHORD);
+        harness.send(&[0x90, 0x57, 0x7F]);
+        harness.send_after(51, &[0x90, 0x58, 0x7F]);
+        harness.wait(50);
+        assert_eq!(harness.latches(), (true, true, false));
+        harness.send(&[0x90, 0x57, 0x00]);
+        harness.send(&[0x90, 0x58, 0x00]);
+
+        harness.send_after(1000, &[0x90, 0x58, 0x7F]);
+        harness.send_after(50, &[0x90, 0x57, 0x7F]);
+        harness.wait(50);
+        assert_eq!(harness.latches(), (true, true, true));
+    }
+
+    #[test]
+    fn chord_after_releasing_a_key() {
+        let mut harness = Harness::new(CHORD);
+        // Pressed and released before the other key, so not held together
+        harness.send(&[0x90, 0x57, 0x7F]);
+        harness.send(&[0x90, 0x57, 0x00]);
+        harness.wait(50);
+        assert_eq!(harness.latches(), (true, false, false));
+        harness.send(&[0x90, 0x58, 0x7F]);
+        // Pressed again while the other one is still held
+        harness.send(&[0x90, 0x57, 0x7F]);
+        harness.wait(50);
+        assert_eq!(harness.latches(), (true, false, true));
+    }
+
+    #[test]
+    fn chord_sees_releases_that_go_to_a_layer() {
+        let mut harness = Harness::new(&format!(
+            r#"
+            {CHORD}
+
+            [[layers]]
+            name = "shift"
+            button = {{ message = "note", channel = 0, note = 0x54 }}
+
+            [[layers.controls]]
+            type = "button"
+            command = {{ message = "note", channel = 0, note = 0x57 }}
+            press = {{ name = "log", message = "shifted" }}
+            "#
+        ));
+        harness.send(&[0x90, 0x57, 0x7F]);
+        harness.send(&[0x90, 0x54, 0x7F]);
+        // The layer's button gets the release of 0x57
+        harness.send(&[0x90, 0x57, 0x00]);
+        harness.send(&[0x90, 0x54, 0x00]);
+        // So 0x58 on its own isn't a chord
+        harness.send(&[0x90, 0x58, 0x7F]);
+        harness.wait(50);
+        assert_eq!(harness.latches(), (true, true, false));
+    }
+
+    #[test]
+    fn chord_cancels_pending_tap() {
+        let mut harness = Harness::new(&format!(
+            r#"
+            {CHORD}
+
+            [[controls]]
+            type = "button"
+            command = {{ message = "note", channel = 0, note = 0x58 }}
+            tap = {{ name = "session_volume", process = "Discord*" }}
+            double_tap = {{ name = "log", message = "double tap" }}
+            double_tap_ms = 1
+            "#
+        ));
+        {
+            let mut audio = harness.audio.borrow_mut();
+            audio.add_device("speakers", "Speakers", Flow::Render);
+            audio
+                .add_session("speakers", "a", Some("Discord"), 1)
+                .unwrap();
+        }
+        // A tap waiting to see if it becomes a double tap, when the key
+        // completes a chord instead
+        harness.send(&[0x90, 0x58, 0x7F]);
+        harness.send(&[0x90, 0x58, 0x00]);
+        harness.send(&[0x90, 0x57, 0x7F]);
+        harness.send(&[0x90, 0x58, 0x7F]);
+        assert_eq!(harness.latches(), (false, false, true));
+        assert_eq!(harness.mappings.next_deadline(), None);
+        harness.wait(1000);
+        assert!(!harness.muted(&session("a")));
+    }
+}
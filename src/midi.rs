@@ -1 +1,13 @@
+use midir::{MidiOutput, MidiOutputConnection};
 
+use crate::error::{Error, Result};
+
+pub(crate) fn connect_output(port_name: &str) -> Result<MidiOutputConnection> {
+    let midi_out = MidiOutput::new("MIDI Windows Controller")?;
+    let out_ports = midi_out.ports();
+    let out_port = out_ports
+        .iter()
+        .find(|port| midi_out.port_name(port).is_ok_and(|name| name == port_name));
+    let out_port = out_port.ok_or(Error::DeviceNotFound)?;
+    Ok(midi_out.connect(out_port, "feedback")?)
+}
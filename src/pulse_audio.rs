@@ -0,0 +1,758 @@
+use std::{
+    collections::HashMap,
+    io::{BufRead, BufReader},
+    process::{Child, Command, Stdio},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+};
+
+use log::debug;
+use serde::{de::DeserializeOwned, Deserialize};
+
+use crate::{
+    audio::{AudioBackend, Change, Device, Flow, Role, Session, Target},
+    error::{Error, Result},
+};
+
+/// What PulseAudio calls 100%
+const VOLUME_NORM: f32 = 65536.0;
+
+#[derive(Debug, Deserialize)]
+struct ChannelVolume {
+    value: u32,
+}
+
+/// A sink or source, as listed by `pactl --format=json`
+#[derive(Debug, Deserialize)]
+struct ListedDevice {
+    index: u32,
+    name: String,
+    description: String,
+    mute: bool,
+    volume: HashMap<String, ChannelVolume>,
+    #[serde(default)]
+    properties: HashMap<String, serde_json::Value>,
+}
+
+/// A sink input, as listed by `pactl --format=json`
+#[derive(Debug, Deserialize)]
+struct ListedSession {
+    index: u32,
+    sink: u32,
+    mute: bool,
+    volume: HashMap<String, ChannelVolume>,
+    #[serde(default)]
+    properties: HashMap<String, serde_json::Value>,
+}
+
+#[derive(Clone, Debug)]
+struct Level {
+    volume: f32,
+    mute: bool,
+}
+
+/// Runs `pactl` with the given arguments and returns its output. Stands in
+/// for the real thing in tests.
+type Pactl = dyn Fn(&[&str]) -> Result<String>;
+
+#[derive(Debug, Default)]
+struct Snapshot {
+    devices: Vec<(Device, Level)>,
+    sessions: Vec<(Session, Level)>,
+    default_sink: Option<String>,
+    default_source: Option<String>,
+}
+
+impl Snapshot {
+    fn load(pactl: &Pactl) -> Result<Self> {
+        let sinks: Vec<ListedDevice> = pactl_json(pactl, &["list", "sinks"])?;
+        let sources: Vec<ListedDevice> = pactl_json(pactl, &["list", "sources"])?;
+        let sink_inputs: Vec<ListedSession> = pactl_json(pactl, &["list", "sink-inputs"])?;
+        let sink_names: HashMap<u32, String> = sinks
+            .iter()
+            .map(|sink| (sink.index, sink.name.clone()))
+            .collect();
+        let devices = sinks
+            .into_iter()
+            .map(|sink| (sink, Flow::Render))
+            .chain(
+                sources
+                    .into_iter()
+                    // Every sink has a source that records what it plays,
+                    // which is not what anyone means by a microphone
+                    .filter(|source| {
+                        property(&source.properties, "device.class") != Some("monitor")
+                    })
+                    .map(|source| (source, Flow::Capture)),
+            )
+            .map(|(listed, flow)| {
+                let level = Level {
+                    volume: average(&listed.volume),
+                    mute: listed.mute,
+                };
+                let device = Device {
+                    id: listed.name,
+                    name: listed.description,
+                    flow,
+                    active: true,
+                };
+                (device, level)
+            })
+            .collect();
+        let sessions = sink_inputs
+            .into_iter()
+            .filter_map(|listed| {
+                let device_id = sink_names.get(&listed.sink)?.clone();
+                let process_name =
+                    property(&listed.properties, "application.process.binary").map(str::to_string);
+                let name = property(&listed.properties, "application.name")
+                    .map(str::to_string)
+                    .or_else(|| process_name.clone())
+                    .unwrap_or_else(|| "Unknown".to_string());
+                let pid = property(&listed.properties, "application.process.id")
+                    .and_then(|pid| pid.parse().ok())
+                    .unwrap_or_default();
+                let level = Level {
+                    volume: average(&listed.volume),
+                    mute: listed.mute,
+                };
+                let session = Session {
+                    id: listed.index.to_string(),
+                    device_id,
+                    name,
+                    process_name,
+                    pid,
+                };
+                Some((session, level))
+            })
+            .collect();
+        Ok(Self {
+            devices,
+            sessions,
+            default_sink: pactl(&["get-default-sink"]).ok(),
+            default_source: pactl(&["get-default-source"]).ok(),
+        })
+    }
+
+    fn device(&self, id: &str) -> Option<&Device> {
+        self.devices
+            .iter()
+            .map(|(device, _)| device)
+            .find(|device| device.id == id)
+    }
+
+    fn device_name(&self, id: &str) -> String {
+        self.device(id)
+            .map_or_else(|| id.to_string(), |device| device.name.clone())
+    }
+
+    fn default_id(&self, flow: Flow) -> Option<&String> {
+        match flow {
+            Flow::Render => self.default_sink.as_ref(),
+            Flow::Capture => self.default_source.as_ref(),
+        }
+    }
+
+    /// What changed going from `self` to `new`
+    fn changes(&self, new: &Self) -> Vec<Change> {
+        let mut changes = Vec::new();
+        for (device, _) in &new.devices {
+            if self.device(&device.id).is_none() {
+                changes.push(Change::DeviceAdded(device.name.clone()));
+            }
+        }
+        for (device, _) in &self.devices {
+            if new.device(&device.id).is_none() {
+                changes.push(Change::DeviceRemoved(device.name.clone()));
+            }
+        }
+        for (session, level) in &new.sessions {
+            let device = new.device_name(&session.device_id);
+            match self.sessions.iter().find(|(old, _)| old.id == session.id) {
+                None => changes.push(Change::SessionCreated {
+                    device,
+                    session: session.name.clone(),
+                }),
+                Some((_, old_level)) if old_level.mute != level.mute => {
+                    changes.push(Change::Mute {
+                        device,
+                        session: session.name.clone(),
+                        mute: level.mute,
+                    });
+                }
+                Some(_) => {}
+            }
+        }
+        for (session, _) in &self.sessions {
+            if !new.sessions.iter().any(|(new, _)| new.id == session.id) {
+                changes.push(Change::SessionRemoved {
+                    device: self.device_name(&session.device_id),
+                    session: session.name.clone(),
+                });
+            }
+        }
+        for flow in [Flow::Render, Flow::Capture] {
+            if let Some(id) = new.default_id(flow) {
+                if self.default_id(flow) != Some(id) {
+                    changes.push(Change::DefaultDevice {
+                        flow,
+                        role: Role::default(),
+                        device: new.device_name(id),
+                    });
+                }
+            }
+        }
+        changes
+    }
+
+    /// Sets the volume of `target` with `pactl`, and in here right away.
+    fn set_volume(&mut self, pactl: &Pactl, target: &Target, volume: f32) -> Result<()> {
+        let volume = volume.clamp(0.0, 1.0);
+        #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+        let raw = (volume * VOLUME_NORM).round() as u32;
+        self.set(pactl, target, "volume", &raw.to_string())?;
+        self.level_mut(target)?.volume = volume;
+        Ok(())
+    }
+
+    fn set_muted(&mut self, pactl: &Pactl, target: &Target, muted: bool) -> Result<()> {
+        self.set(pactl, target, "mute", if muted { "1" } else { "0" })?;
+        self.level_mut(target)?.mute = muted;
+        Ok(())
+    }
+
+    /// Runs the `pactl` command that sets `what` for `target`, e.g.
+    /// `set-sink-mute`
+    fn set(&self, pactl: &Pactl, target: &Target, what: &str, value: &str) -> Result<()> {
+        let (kind, id) = match target {
+            Target::Device(id) => {
+                let device = self
+                    .device(id)
+                    .ok_or_else(|| Error::AudioTargetNotFound(target.clone()))?;
+                match device.flow {
+                    Flow::Render => ("sink", id),
+                    Flow::Capture => ("source", id),
+                }
+            }
+            Target::Session(id) => ("sink-input", id),
+        };
+        pactl(&[&format!("set-{kind}-{what}"), id, value])?;
+        Ok(())
+    }
+
+    fn level(&self, target: &Target) -> Result<&Level> {
+        let level = match target {
+            Target::Device(id) => self
+                .devices
+                .iter()
+                .find(|(device, _)| device.id == *id)
+                .map(|(_, level)| level),
+            Target::Session(id) => self
+                .sessions
+                .iter()
+                .find(|(session, _)| session.id == *id)
+                .map(|(_, level)| level),
+        };
+        level.ok_or_else(|| Error::AudioTargetNotFound(target.clone()))
+    }
+
+    fn level_mut(&mut self, target: &Target) -> Result<&mut Level> {
+        let level = match target {
+            Target::Device(id) => self
+                .devices
+                .iter_mut()
+                .find(|(device, _)| device.id == *id)
+                .map(|(_, level)| level),
+            Target::Session(id) => self
+                .sessions
+                .iter_mut()
+                .find(|(session, _)| session.id == *id)
+                .map(|(_, level)| level),
+        };
+        level.ok_or_else(|| Error::AudioTargetNotFound(target.clone()))
+    }
+}
+
+/// PulseAudio, or PipeWire through its PulseAudio compatibility, by way of
+/// `pactl`. Sinks and sources are devices and sink inputs are sessions. There
+/// is only one default device for each direction, which stands in for all
+/// roles.
+pub struct PulseAudio {
+    snapshot: Snapshot,
+    /// Set by the subscription until `update` has picked up the changes, so
+    /// that a burst of events, e.g. from dragging a volume slider, only
+    /// causes a single update
+    dirty: Arc<AtomicBool>,
+    subscription: Child,
+}
+
+impl PulseAudio {
+    /// `on_change` gets called from another thread, so it should only pass
+    /// on that `update` has work to do. Needs `pactl` 16 or newer for its
+    /// JSON output.
+    pub fn new<F>(on_change: F) -> Result<Self>
+    where
+        F: Fn() + Send + Sync + 'static,
+    {
+        let snapshot = Snapshot::load(&pactl)?;
+        let mut subscription = Command::new("pactl")
+            .arg("subscribe")
+            .stdout(Stdio::piped())
+            .spawn()?;
+        let stdout = subscription
+            .stdout
+            .take()
+            .ok_or_else(|| Error::Pactl("no output from subscription".to_string()))?;
+        let dirty = Arc::new(AtomicBool::new(false));
+        let subscription_dirty = dirty.clone();
+        thread::spawn(move || {
+            for line in BufReader::new(stdout).lines() {
+                let Ok(line) = line else {
+                    break;
+                };
+                // Clients, modules and cards coming and going don't change
+                // anything we keep track of
+                if ["sink", "source", "server"]
+                    .iter()
+                    .any(|facility| line.contains(&format!(" {facility}")))
+                    && !subscription_dirty.swap(true, Ordering::SeqCst)
+                {
+                    on_change();
+                }
+            }
+            debug!("pactl subscription ended");
+        });
+        Ok(Self {
+            snapshot,
+            dirty,
+            subscription,
+        })
+    }
+}
+
+impl Drop for PulseAudio {
+    fn drop(&mut self) {
+        // Nothing sensible to do about a failure while dropping
+        let _ = self.subscription.kill();
+        let _ = self.subscription.wait();
+    }
+}
+
+impl AudioBackend for PulseAudio {
+    fn update(&mut self) -> Result<Vec<Change>> {
+        // Cleared first, so that anything that happens while loading gets
+        // picked up by the next update
+        self.dirty.store(false, Ordering::SeqCst);
+        let snapshot = Snapshot::load(&pactl)?;
+        let changes = self.snapshot.changes(&snapshot);
+        self.snapshot = snapshot;
+        Ok(changes)
+    }
+
+    fn devices(&self) -> Vec<Device> {
+        self.snapshot
+            .devices
+            .iter()
+            .map(|(device, _)| device.clone())
+            .collect()
+    }
+
+    fn sessions(&self) -> Vec<Session> {
+        self.snapshot
+            .sessions
+            .iter()
+            .map(|(session, _)| session.clone())
+            .collect()
+    }
+
+    fn default_device(&self, flow: Flow, _role: Role) -> Option<Device> {
+        let id = self.snapshot.default_id(flow)?;
+        self.snapshot.device(id).cloned()
+    }
+
+    fn set_default_device(&mut self, id: &str, _role: Role) -> Result<()> {
+        let device = self
+            .snapshot
+            .device(id)
+            .ok_or_else(|| Error::AudioTargetNotFound(Target::Device(id.to_string())))?;
+        let command = match device.flow {
+            Flow::Render => "set-default-sink",
+            Flow::Capture => "set-default-source",
+        };
+        pactl(&[command, id])?;
+        Ok(())
+    }
+
+    // Our own changes are applied to the snapshot right away, so it is never
+    // behind on those
+    fn volume(&self, target: &Target) -> Result<f32> {
+        Ok(self.snapshot.level(target)?.volume)
+    }
+
+    fn set_volume(&mut self, target: &Target, volume: f32) -> Result<()> {
+        self.snapshot.set_volume(&pactl, target, volume)
+    }
+
+    fn muted(&self, target: &Target) -> Result<bool> {
+        Ok(self.snapshot.level(target)?.mute)
+    }
+
+    fn set_muted(&mut self, target: &Target, muted: bool) -> Result<()> {
+        self.snapshot.set_muted(&pactl, target, muted)
+    }
+}
+
+fn property<'a>(properties: &'a HashMap<String, serde_json::Value>, key: &str) -> Option<&'a str> {
+    properties.get(key)?.as_str()
+}
+
+/// The volume of all channels together, from 0.0 to 1.0
+fn average(channels: &HashMap<String, ChannelVolume>) -> f32 {
+    if channels.is_empty() {
+        return 0.0;
+    }
+    #[allow(clippy::cast_precision_loss)]
+    let total = channels
+        .values()
+        .map(|channel| channel.value as f32)
+        .sum::<f32>()
+        / channels.len() as f32;
+    (total / VOLUME_NORM).clamp(0.0, 1.0)
+}
+
+fn pactl(args: &[&str]) -> Result<String> {
+    let output = Command::new("pactl").args(args).output()?;
+    if !output.status.success() {
+        return Err(Error::Pactl(format!(
+            "pactl {}: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+fn pactl_json<T: DeserializeOwned>(pactl: &Pactl, args: &[&str]) -> Result<T> {
+    let mut json_args = vec!["--format=json"];
+    json_args.extend_from_slice(args);
+    Ok(serde_json::from_str(&pactl(&json_args)?)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc};
+
+    use super::*;
+
+    /// Trimmed down from what `pactl --format=json list sinks` prints
+    const SINKS: &str = r#"[
+        {
+            "index": 56,
+            "state": "RUNNING",
+            "name": "alsa_output.pci-0000_00_1f.3.analog-stereo",
+            "description": "Built-in Audio Analog Stereo",
+            "mute": false,
+            "volume": {
+                "front-left": {"value": 65536, "value_percent": "100%", "db": "0.00 dB"},
+                "front-right": {"value": 32768, "value_percent": "50%", "db": "-18.06 dB"}
+            },
+            "properties": {"device.class": "sound", "device.api": "alsa"}
+        },
+        {
+            "index": 71,
+            "state": "SUSPENDED",
+            "name": "bluez_output.00_1B_66_AA_BB_CC.1",
+            "description": "Headset",
+            "mute": true,
+            "volume": {
+                "mono": {"value": 98304, "value_percent": "150%", "db": "10.57 dB"}
+            }
+        }
+    ]"#;
+
+    const SOURCES: &str = r#"[
+        {
+            "index": 57,
+            "name": "alsa_output.pci-0000_00_1f.3.analog-stereo.monitor",
+            "description": "Monitor of Built-in Audio Analog Stereo",
+            "mute": false,
+            "volume": {"front-left": {"value": 65536}, "front-right": {"value": 65536}},
+            "properties": {"device.class": "monitor"}
+        },
+        {
+            "index": 58,
+            "name": "alsa_input.pci-0000_00_1f.3.analog-stereo",
+            "description": "Built-in Audio Microphone",
+            "mute": true,
+            "volume": {"front-left": {"value": 16384}, "front-right": {"value": 16384}},
+            "properties": {"device.class": "sound"}
+        }
+    ]"#;
+
+    const SINK_INPUTS: &str = r#"[
+        {
+            "index": 203,
+            "sink": 56,
+            "mute": false,
+            "volume": {"front-left": {"value": 65536}, "front-right": {"value": 65536}},
+            "properties": {
+                "application.name": "Spotify",
+                "application.process.binary": "spotify",
+                "application.process.id": "4242"
+            }
+        },
+        {
+            "index": 204,
+            "sink": 71,
+            "mute": true,
+            "volume": {"mono": {"value": 0}},
+            "properties": {"application.process.binary": "Discord"}
+        },
+        {
+            "index": 205,
+            "sink": 56,
+            "mute": false,
+            "volume": {"mono": {"value": 65536}},
+            "properties": {"application.process.id": "not a pid"}
+        },
+        {
+            "index": 206,
+            "sink": 99,
+            "mute": false,
+            "volume": {"mono": {"value": 65536}},
+            "properties": {}
+        }
+    ]"#;
+
+    /// A `pactl` that answers with the fixtures above, with `sink_inputs`
+    /// instead of `SINK_INPUTS` and `default_sink` as the default sink
+    fn fake_pactl(sink_inputs: &str, default_sink: &str) -> impl Fn(&[&str]) -> Result<String> {
+        let sink_inputs = sink_inputs.to_string();
+        let default_sink = default_sink.to_string();
+        move |args| match args {
+            ["--format=json", "list", "sinks"] => Ok(SINKS.to_string()),
+            ["--format=json", "list", "sources"] => Ok(SOURCES.to_string()),
+            ["--format=json", "list", "sink-inputs"] => Ok(sink_inputs.clone()),
+            ["get-default-sink"] => Ok(default_sink.clone()),
+            ["get-default-source"] => Err(Error::Pactl("no default source".to_string())),
+            _ => panic!("unexpected pactl {args:?}"),
+        }
+    }
+
+    fn load(sink_inputs: &str, default_sink: &str) -> Snapshot {
+        Snapshot::load(&fake_pactl(sink_inputs, default_sink)).unwrap()
+    }
+
+    fn assert_volume(actual: f32, expected: f32) {
+        assert!(
+            (actual - expected).abs() < 1e-4,
+            "volume is {actual} instead of {expected}"
+        );
+    }
+
+    #[test]
+    fn devices() {
+        let snapshot = load(SINK_INPUTS, "alsa_output.pci-0000_00_1f.3.analog-stereo");
+        let devices: Vec<_> = snapshot
+            .devices
+            .iter()
+            .map(|(device, _)| (device.id.as_str(), device.name.as_str(), device.flow))
+            .collect();
+        // Without the monitor source
+        assert_eq!(
+            devices,
+            [
+                (
+                    "alsa_output.pci-0000_00_1f.3.analog-stereo",
+                    "Built-in Audio Analog Stereo",
+                    Flow::Render
+                ),
+                ("bluez_output.00_1B_66_AA_BB_CC.1", "Headset", Flow::Render),
+                (
+                    "alsa_input.pci-0000_00_1f.3.analog-stereo",
+                    "Built-in Audio Microphone",
+                    Flow::Capture
+                ),
+            ]
+        );
+        let levels: Vec<_> = snapshot.devices.iter().map(|(_, level)| level).collect();
+        // Channels are averaged, and anything over 100% counts as 100%
+        assert_volume(levels[0].volume, 0.75);
+        assert!(!levels[0].mute);
+        assert_volume(levels[1].volume, 1.0);
+        assert!(levels[1].mute);
+        assert_volume(levels[2].volume, 0.25);
+        assert!(levels[2].mute);
+        assert_eq!(
+            snapshot.default_id(Flow::Render).map(String::as_str),
+            Some("alsa_output.pci-0000_00_1f.3.analog-stereo")
+        );
+        assert_eq!(snapshot.default_id(Flow::Capture), None);
+    }
+
+    #[test]
+    fn sessions() {
+        let snapshot = load(SINK_INPUTS, "");
+        let sessions: Vec<_> = snapshot
+            .sessions
+            .iter()
+            .map(|(session, _)| session.clone())
+            .collect();
+        // The one on a sink that isn't listed is left out
+        assert_eq!(
+            sessions,
+            [
+                Session {
+                    id: "203".to_string(),
+                    device_id: "alsa_output.pci-0000_00_1f.3.analog-stereo".to_string(),
+                    name: "Spotify".to_string(),
+                    process_name: Some("spotify".to_string()),
+                    pid: 4242,
+                },
+                Session {
+                    id: "204".to_string(),
+                    device_id: "bluez_output.00_1B_66_AA_BB_CC.1".to_string(),
+                    name: "Discord".to_string(),
+                    process_name: Some("Discord".to_string()),
+                    pid: 0,
+                },
+                Session {
+                    id: "205".to_string(),
+                    device_id: "alsa_output.pci-0000_00_1f.3.analog-stereo".to_string(),
+                    name: "Unknown".to_string(),
+                    process_name: None,
+                    pid: 0,
+                },
+            ]
+        );
+        assert_volume(snapshot.sessions[1].1.volume, 0.0);
+        assert!(snapshot.sessions[1].1.mute);
+    }
+
+    #[test]
+    fn malformed_output() {
+        let pactl = |args: &[&str]| match args {
+            ["--format=json", "list", "sinks"] => Ok("[{\"index\": 1}]".to_string()),
+            _ => Ok("[]".to_string()),
+        };
+        assert!(matches!(Snapshot::load(&pactl), Err(Error::Json(_))));
+    }
+
+    #[test]
+    fn no_changes() {
+        let old = load(SINK_INPUTS, "alsa_output.pci-0000_00_1f.3.analog-stereo");
+        let new = load(SINK_INPUTS, "alsa_output.pci-0000_00_1f.3.analog-stereo");
+        assert_eq!(old.changes(&new), []);
+    }
+
+    #[test]
+    fn changes() {
+        let old = load(SINK_INPUTS, "alsa_output.pci-0000_00_1f.3.analog-stereo");
+        // Spotify is gone, Discord got unmuted and Firefox started playing
+        let sink_inputs = r#"[
+            {
+                "index": 204,
+                "sink": 71,
+                "mute": false,
+                "volume": {"mono": {"value": 0}},
+                "properties": {"application.process.binary": "Discord"}
+            },
+            {
+                "index": 205,
+                "sink": 56,
+                "mute": false,
+                "volume": {"mono": {"value": 65536}},
+                "properties": {}
+            },
+            {
+                "index": 210,
+                "sink": 71,
+                "mute": false,
+                "volume": {"mono": {"value": 65536}},
+                "properties": {"application.name": "Firefox"}
+            }
+        ]"#;
+        let new = load(sink_inputs, "bluez_output.00_1B_66_AA_BB_CC.1");
+        assert_eq!(
+            old.changes(&new),
+            [
+                Change::Mute {
+                    device: "Headset".to_string(),
+                    session: "Discord".to_string(),
+                    mute: false,
+                },
+                Change::SessionCreated {
+                    device: "Headset".to_string(),
+                    session: "Firefox".to_string(),
+                },
+                Change::SessionRemoved {
+                    device: "Built-in Audio Analog Stereo".to_string(),
+                    session: "Spotify".to_string(),
+                },
+                Change::DefaultDevice {
+                    flow: Flow::Render,
+                    role: Role::Console,
+                    device: "Headset".to_string(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn devices_coming_and_going() {
+        let mut old = load("[]", "");
+        old.devices.remove(1);
+        let mut new = load("[]", "");
+        new.devices.remove(0);
+        assert_eq!(
+            old.changes(&new),
+            [
+                Change::DeviceAdded("Headset".to_string()),
+                Change::DeviceRemoved("Built-in Audio Analog Stereo".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn set_volume_clamps() {
+        let mut snapshot = load(SINK_INPUTS, "");
+        let commands = Rc::new(RefCell::new(Vec::new()));
+        let pactl = {
+            let commands = commands.clone();
+            move |args: &[&str]| {
+                commands.borrow_mut().push(args.join(" "));
+                Ok(String::new())
+            }
+        };
+        let spotify = Target::Session("203".to_string());
+        snapshot.set_volume(&pactl, &spotify, 1.5).unwrap();
+        assert_volume(snapshot.level(&spotify).unwrap().volume, 1.0);
+        let headset = Target::Device("bluez_output.00_1B_66_AA_BB_CC.1".to_string());
+        snapshot.set_volume(&pactl, &headset, -0.5).unwrap();
+        assert_volume(snapshot.level(&headset).unwrap().volume, 0.0);
+        snapshot.set_muted(&pactl, &headset, false).unwrap();
+        assert!(!snapshot.level(&headset).unwrap().mute);
+        assert_eq!(
+            *commands.borrow(),
+            [
+                "set-sink-input-volume 203 65536",
+                "set-sink-volume bluez_output.00_1B_66_AA_BB_CC.1 0",
+                "set-sink-mute bluez_output.00_1B_66_AA_BB_CC.1 0",
+            ]
+        );
+    }
+
+    #[test]
+    fn set_unknown_target() {
+        let mut snapshot = load(SINK_INPUTS, "");
+        let pactl = |_: &[&str]| -> Result<String> { panic!("pactl shouldn't run") };
+        let target = Target::Device("gone".to_string());
+        assert!(matches!(
+            snapshot.set_volume(&pactl, &target, 0.5),
+            Err(Error::AudioTargetNotFound(_))
+        ));
+    }
+}
@@ -1 +1,234 @@
+use std::{
+    ptr,
+    sync::{mpsc, Arc},
+};
 
+use windows::{
+    core::PCWSTR,
+    Win32::{
+        Foundation::TRUE,
+        Media::Audio::{
+            eCapture, eCommunications, eConsole, eMultimedia, eRender,
+            EDataFlow as WindowsEDataFlow, ERole as WindowsERole, Endpoints::IAudioEndpointVolume,
+            IMMDeviceEnumerator, ISimpleAudioVolume, MMDeviceEnumerator,
+        },
+        System::Com::{CoCreateInstance, CoInitializeEx, CLSCTX_ALL, COINIT_MULTITHREADED},
+    },
+};
+
+use crate::{
+    audio::{AudioBackend, Change, Device, Flow, Role, Session, Target},
+    error::{Error, Result},
+};
+use devices::{AudioEvent, DeviceInfo, DeviceMap};
+pub use foreground::watch_foreground;
+use policy_config::{IPolicyConfig, CPOLICY_CONFIG_CLIENT};
+
+mod devices;
+mod foreground;
+mod policy_config;
+
+impl From<Flow> for WindowsEDataFlow {
+    fn from(value: Flow) -> WindowsEDataFlow {
+        match value {
+            Flow::Render => eRender,
+            Flow::Capture => eCapture,
+        }
+    }
+}
+
+impl From<Role> for WindowsERole {
+    fn from(value: Role) -> WindowsERole {
+        match value {
+            Role::Console => eConsole,
+            Role::Multimedia => eMultimedia,
+            Role::Communications => eCommunications,
+        }
+    }
+}
+
+/// Volume and mute, the same for sessions and endpoints. Volume goes from 0.0
+/// to 1.0.
+trait Volume {
+    fn volume(&self) -> Result<f32>;
+    fn set_volume(&self, volume: f32) -> Result<()>;
+    fn muted(&self) -> Result<bool>;
+    fn set_muted(&self, muted: bool) -> Result<()>;
+}
+
+impl Volume for ISimpleAudioVolume {
+    fn volume(&self) -> Result<f32> {
+        Ok(unsafe { self.GetMasterVolume() }?)
+    }
+
+    fn set_volume(&self, volume: f32) -> Result<()> {
+        Ok(unsafe { self.SetMasterVolume(volume, ptr::null()) }?)
+    }
+
+    fn muted(&self) -> Result<bool> {
+        Ok(unsafe { self.GetMute() }? == TRUE)
+    }
+
+    fn set_muted(&self, muted: bool) -> Result<()> {
+        Ok(unsafe { self.SetMute(muted, ptr::null()) }?)
+    }
+}
+
+impl Volume for IAudioEndpointVolume {
+    fn volume(&self) -> Result<f32> {
+        Ok(unsafe { self.GetMasterVolumeLevelScalar() }?)
+    }
+
+    fn set_volume(&self, volume: f32) -> Result<()> {
+        Ok(unsafe { self.SetMasterVolumeLevelScalar(volume, ptr::null()) }?)
+    }
+
+    fn muted(&self) -> Result<bool> {
+        Ok(unsafe { self.GetMute() }? == TRUE)
+    }
+
+    fn set_muted(&self, muted: bool) -> Result<()> {
+        Ok(unsafe { self.SetMute(muted, ptr::null()) }?)
+    }
+}
+
+/// The devices and sessions of the Windows audio stack, kept up to date with
+/// the notifications Windows sends. All of it has to happen on the thread
+/// that created this.
+pub struct WindowsAudio {
+    policy_config: IPolicyConfig,
+    devices: DeviceMap,
+    events: mpsc::Receiver<AudioEvent>,
+}
+
+impl WindowsAudio {
+    /// `on_change` gets called from threads owned by Windows, so it should
+    /// only pass on that `update` has work to do.
+    pub fn new<F>(on_change: F) -> Result<Self>
+    where
+        F: Fn() + Send + Sync + 'static,
+    {
+        unsafe { CoInitializeEx(None, COINIT_MULTITHREADED).ok() }?;
+        let enumerator = unsafe {
+            CoCreateInstance::<_, IMMDeviceEnumerator>(&MMDeviceEnumerator, None, CLSCTX_ALL)
+        }?;
+        let policy_config = unsafe {
+            CoCreateInstance::<_, IPolicyConfig>(&CPOLICY_CONFIG_CLIENT, None, CLSCTX_ALL)
+        }?;
+        let (event_tx, events) = mpsc::channel();
+        let on_event = move |event| {
+            // Only fails once we're gone, and then there's nothing to update
+            if event_tx.send(event).is_ok() {
+                on_change();
+            }
+        };
+        Ok(Self {
+            policy_config,
+            devices: DeviceMap::new(&enumerator, Arc::new(on_event))?,
+            events,
+        })
+    }
+
+    fn session_volume(&self, id: &str) -> Result<&ISimpleAudioVolume> {
+        self.devices
+            .devices()
+            .flat_map(DeviceInfo::sessions)
+            .find(|session| session.instance_id() == id)
+            .map(devices::SessionInfo::simple_volume)
+            .ok_or_else(|| Error::AudioTargetNotFound(Target::Session(id.to_string())))
+    }
+
+    fn endpoint_volume(&self, id: &str) -> Result<IAudioEndpointVolume> {
+        let device = self
+            .devices
+            .devices()
+            .find(|device| device.id() == id)
+            .ok_or_else(|| Error::AudioTargetNotFound(Target::Device(id.to_string())))?;
+        Ok(unsafe {
+            device
+                .device()
+                .Activate::<IAudioEndpointVolume>(CLSCTX_ALL, None)
+        }?)
+    }
+
+    fn target_volume(&self, target: &Target) -> Result<Box<dyn Volume>> {
+        Ok(match target {
+            Target::Device(id) => Box::new(self.endpoint_volume(id)?),
+            Target::Session(id) => Box::new(self.session_volume(id)?.clone()),
+        })
+    }
+}
+
+impl AudioBackend for WindowsAudio {
+    fn update(&mut self) -> Result<Vec<Change>> {
+        let mut changes = Vec::new();
+        while let Ok(event) = self.events.try_recv() {
+            if let Some(change) = self.devices.handle_event(event)? {
+                changes.push(change);
+            }
+        }
+        Ok(changes)
+    }
+
+    fn devices(&self) -> Vec<Device> {
+        self.devices.devices().map(device).collect()
+    }
+
+    fn sessions(&self) -> Vec<Session> {
+        self.devices
+            .devices()
+            .filter(|device| device.is_active() && device.flow() == Flow::Render)
+            .flat_map(|device| {
+                device.sessions().map(|session| Session {
+                    id: session.instance_id().to_string(),
+                    device_id: device.id().to_string(),
+                    name: session.display_name().to_string(),
+                    process_name: session.process_name().map(str::to_string),
+                    pid: session.pid(),
+                })
+            })
+            .collect()
+    }
+
+    fn default_device(&self, flow: Flow, role: Role) -> Option<Device> {
+        self.devices.get_default_device(flow, role).map(device)
+    }
+
+    fn set_default_device(&mut self, id: &str, role: Role) -> Result<()> {
+        let id = wide_string(id);
+        Ok(unsafe {
+            self.policy_config
+                .SetDefaultEndpoint(PCWSTR(id.as_ptr()), role.into())
+        }
+        .ok()?)
+    }
+
+    fn volume(&self, target: &Target) -> Result<f32> {
+        self.target_volume(target)?.volume()
+    }
+
+    fn set_volume(&mut self, target: &Target, volume: f32) -> Result<()> {
+        self.target_volume(target)?.set_volume(volume)
+    }
+
+    fn muted(&self, target: &Target) -> Result<bool> {
+        self.target_volume(target)?.muted()
+    }
+
+    fn set_muted(&mut self, target: &Target, muted: bool) -> Result<()> {
+        self.target_volume(target)?.set_muted(muted)
+    }
+}
+
+fn device(device: &DeviceInfo) -> Device {
+    Device {
+        id: device.id().to_string(),
+        name: device.name().to_string(),
+        flow: device.flow(),
+        active: device.is_active(),
+    }
+}
+
+fn wide_string(input: &str) -> Vec<u16> {
+    input.encode_utf16().chain(Some(0)).collect()
+}
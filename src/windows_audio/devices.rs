@@ -0,0 +1,809 @@
+// The Windows constants that get matched on are named like in C
+#![allow(non_upper_case_globals)]
+
+use std::{collections::HashMap, sync::Arc};
+
+use log::debug;
+use sysinfo::{Pid, ProcessRefreshKind, System};
+use windows::{
+    core::{implement, Interface, GUID, HRESULT, PCWSTR},
+    Win32::{
+        Foundation::{BOOL, S_OK, TRUE},
+        Media::Audio::{
+            eAll, eCapture, eCommunications, eConsole, eMultimedia, eRender,
+            AudioSessionDisconnectReason, AudioSessionState, AudioSessionStateActive,
+            AudioSessionStateExpired, AudioSessionStateInactive, DisconnectReasonDeviceRemoval,
+            DisconnectReasonExclusiveModeOverride, DisconnectReasonFormatChanged,
+            DisconnectReasonServerShutdown, DisconnectReasonSessionDisconnected,
+            DisconnectReasonSessionLogoff, EDataFlow as WindowsEDataFlow, ERole as WindowsERole,
+            IAudioSessionControl, IAudioSessionControl2, IAudioSessionEvents,
+            IAudioSessionEvents_Impl, IAudioSessionManager2, IAudioSessionNotification,
+            IAudioSessionNotification_Impl, IMMDevice, IMMDeviceEnumerator, IMMEndpoint,
+            IMMNotificationClient, IMMNotificationClient_Impl, ISimpleAudioVolume, DEVICE_STATE,
+            DEVICE_STATE_ACTIVE, DEVICE_STATE_DISABLED, DEVICE_STATE_NOTPRESENT,
+            DEVICE_STATE_UNPLUGGED,
+        },
+        System::Com::CLSCTX_ALL,
+        UI::Shell::PropertiesSystem::PROPERTYKEY,
+    },
+};
+
+use crate::{
+    audio::{Change, Flow, Role},
+    error::Result,
+    utils::{get_device_name, BAD_VALUE},
+};
+
+use super::wide_string;
+
+pub(super) type EventCallback = Arc<dyn Fn(AudioEvent) + Send + Sync>;
+
+/// Something Windows told us about, to be handled on the thread that owns the
+/// `DeviceMap`.
+#[derive(Debug)]
+pub(super) enum AudioEvent {
+    Device(String, DeviceEvent),
+    /// Device id, session instance id and what happened
+    Session(String, String, SessionEvent),
+}
+
+#[derive(Debug)]
+pub(super) enum DeviceEvent {
+    DefaultDeviceChanged(Flow, Role),
+    DeviceAdded,
+    DeviceRemoved,
+    DeviceStateChanged(DEVICE_STATE),
+    SessionCreated(String),
+}
+
+#[derive(Debug)]
+pub(super) enum SessionEvent {
+    SimpleVolumeChanged { volume: f32, mute: bool },
+    DisplayNameChanged(String),
+    IconPathChanged(String),
+    GroupingParamChanged(u128),
+    StateChanged(SessionState),
+    SessionDisconnected(DisconnectReason),
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, strum::Display)]
+pub(super) enum SessionState {
+    Active,
+    Inactive,
+    Expired,
+}
+
+impl TryFrom<AudioSessionState> for SessionState {
+    type Error = windows::core::Error;
+    fn try_from(value: AudioSessionState) -> windows::core::Result<Self> {
+        match value {
+            AudioSessionStateActive => Ok(SessionState::Active),
+            AudioSessionStateInactive => Ok(SessionState::Inactive),
+            AudioSessionStateExpired => Ok(SessionState::Expired),
+            _ => Err(windows::core::Error::new(
+                HRESULT(BAD_VALUE),
+                "Bad value for state",
+            )),
+        }
+    }
+}
+
+#[derive(Clone, Debug, strum::Display)]
+pub(super) enum DisconnectReason {
+    DeviceRemoval,
+    ServerShutdown,
+    FormatChanged,
+    SessionLogoff,
+    SessionDisconnected,
+    ExclusiveModeOverride,
+    SessionExpired,
+}
+
+impl TryFrom<AudioSessionDisconnectReason> for DisconnectReason {
+    type Error = windows::core::Error;
+    fn try_from(reason: AudioSessionDisconnectReason) -> windows::core::Result<Self> {
+        Ok(match reason {
+            DisconnectReasonDeviceRemoval => DisconnectReason::DeviceRemoval,
+            DisconnectReasonServerShutdown => DisconnectReason::ServerShutdown,
+            DisconnectReasonFormatChanged => DisconnectReason::FormatChanged,
+            DisconnectReasonSessionLogoff => DisconnectReason::SessionLogoff,
+            DisconnectReasonSessionDisconnected => DisconnectReason::SessionDisconnected,
+            DisconnectReasonExclusiveModeOverride => DisconnectReason::ExclusiveModeOverride,
+            _ => {
+                return Err(windows::core::Error::new(
+                    HRESULT(BAD_VALUE),
+                    "Bad value for disconnect reason",
+                ))
+            }
+        })
+    }
+}
+
+#[derive(Clone, Debug, strum::Display)]
+pub(super) enum DeviceState {
+    Active(IAudioSessionManager2),
+    Disabled,
+    NotPresent,
+    Unplugged,
+}
+
+pub(super) struct SessionInfo {
+    instance_id: String,
+    control: IAudioSessionControl,
+    volume: ISimpleAudioVolume,
+    display_name: Option<String>,
+    process_name: Option<String>,
+    pid: u32,
+    mute: bool,
+    // We need to keep a reference to this to keep it alive
+    session_events: IAudioSessionEvents,
+}
+
+impl SessionInfo {
+    fn new(
+        device_id: &str,
+        instance_id: String,
+        control: IAudioSessionControl,
+        control2: &IAudioSessionControl2,
+        on_event: EventCallback,
+    ) -> Result<Self> {
+        let session_events = IAudioSessionEvents::from(AudioSessionEvents {
+            device_id: device_id.to_string(),
+            session_instance_id: instance_id.clone(),
+            on_event,
+        });
+        unsafe { control.RegisterAudioSessionNotification(&session_events) }?;
+        let pid = unsafe { control2.GetProcessId()? };
+        // The process can't change for the lifetime of the session, so
+        // looking it up once is enough
+        let mut system = System::new();
+        let process_name =
+            if system.refresh_process_specifics(Pid::from_u32(pid), ProcessRefreshKind::new()) {
+                system
+                    .process(Pid::from_u32(pid))
+                    .map(|process| process.name().to_string())
+            } else {
+                None
+            };
+        let volume = control.cast::<ISimpleAudioVolume>()?;
+        let mute = unsafe { volume.GetMute() }? == TRUE;
+        let mut session_info = Self {
+            instance_id,
+            control,
+            volume,
+            display_name: None,
+            process_name,
+            pid,
+            mute,
+            session_events,
+        };
+        session_info
+            .set_display_name(unsafe { session_info.control.GetDisplayName()?.to_string() }?);
+        Ok(session_info)
+    }
+
+    fn set_display_name(&mut self, new_display_name: String) {
+        // Most sessions don't set a name, the process name is the next best
+        // thing
+        self.display_name = if new_display_name.is_empty() {
+            self.process_name.clone()
+        } else {
+            Some(new_display_name)
+        };
+    }
+
+    pub(super) fn instance_id(&self) -> &str {
+        &self.instance_id
+    }
+
+    pub(super) fn display_name(&self) -> &str {
+        self.display_name.as_deref().unwrap_or("Unknown")
+    }
+
+    pub(super) fn process_name(&self) -> Option<&str> {
+        self.process_name.as_deref()
+    }
+
+    pub(super) fn pid(&self) -> u32 {
+        self.pid
+    }
+
+    pub(super) fn simple_volume(&self) -> &ISimpleAudioVolume {
+        &self.volume
+    }
+}
+
+impl Drop for SessionInfo {
+    fn drop(&mut self) {
+        // Nothing sensible to do about a failure while dropping
+        let _ = unsafe {
+            self.control
+                .UnregisterAudioSessionNotification(&self.session_events)
+        };
+    }
+}
+
+pub(super) struct DeviceInfo {
+    device: IMMDevice,
+    session_map: HashMap<String, SessionInfo>,
+    id: String,
+    name: String,
+    flow: Flow,
+    state: DeviceState,
+    on_event: EventCallback,
+}
+
+impl DeviceInfo {
+    fn new(device: IMMDevice, on_event: EventCallback) -> Result<Self> {
+        let name = get_device_name(&device)?;
+        let id = unsafe { device.GetId()?.to_string() }?;
+        let flow = Flow::try_from(unsafe { device.cast::<IMMEndpoint>()?.GetDataFlow() }?)?;
+        // windows-rs thinks the return value is DEVICE_STATE, but it's actually HRESULT
+        // See https://github.com/microsoft/windows-rs/issues/3067
+        let mut state: u32 = 0;
+        #[allow(clippy::cast_possible_wrap)]
+        let result = HRESULT(unsafe { device.GetState(&mut state) }.0 as _);
+        let state = if result == S_OK {
+            Self::translate_state(&device, DEVICE_STATE(state))?
+        } else {
+            return Err(windows::core::Error::new(result, "error getting device state").into());
+        };
+        let mut device_info = Self {
+            device,
+            session_map: HashMap::new(),
+            id,
+            name,
+            flow,
+            state,
+            on_event,
+        };
+        device_info.activate()?;
+        Ok(device_info)
+    }
+
+    fn translate_state(device: &IMMDevice, new_state: DEVICE_STATE) -> Result<DeviceState> {
+        Ok(match new_state {
+            DEVICE_STATE_ACTIVE => {
+                let audio_session_manager =
+                    unsafe { device.Activate::<IAudioSessionManager2>(CLSCTX_ALL, None) }?;
+                DeviceState::Active(audio_session_manager)
+            }
+            DEVICE_STATE_DISABLED => DeviceState::Disabled,
+            DEVICE_STATE_NOTPRESENT => DeviceState::NotPresent,
+            DEVICE_STATE_UNPLUGGED => DeviceState::Unplugged,
+            _ => {
+                return Err(
+                    windows::core::Error::new(HRESULT(BAD_VALUE), "Bad value for state").into(),
+                )
+            }
+        })
+    }
+
+    fn set_state(&mut self, new_state: DEVICE_STATE) -> Result<()> {
+        self.state = Self::translate_state(&self.device, new_state)?;
+        self.session_map.clear();
+        self.activate()
+    }
+
+    fn activate(&mut self) -> Result<()> {
+        let DeviceState::Active(audio_session_manager) = &self.state else {
+            return Ok(());
+        };
+        // Register for notifications
+        let audio_session_notification =
+            IAudioSessionNotification::from(AudioSessionNotification {
+                device_id: self.id.clone(),
+                on_event: self.on_event.clone(),
+            });
+        unsafe { audio_session_manager.RegisterSessionNotification(&audio_session_notification) }?;
+        // The notifications won't start until we call `GetCount()` on the
+        // session enumerator, so we do the below after the above
+        for item in all_sessions(audio_session_manager)? {
+            let (instance_id, (control, control2)) = item?;
+            let session_info = SessionInfo::new(
+                &self.id,
+                instance_id,
+                control,
+                &control2,
+                self.on_event.clone(),
+            )?;
+            self.session_map
+                .insert(session_info.instance_id.clone(), session_info);
+        }
+        Ok(())
+    }
+
+    pub(super) fn device(&self) -> &IMMDevice {
+        &self.device
+    }
+
+    pub(super) fn id(&self) -> &str {
+        &self.id
+    }
+
+    pub(super) fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub(super) fn flow(&self) -> Flow {
+        self.flow
+    }
+
+    pub(super) fn is_active(&self) -> bool {
+        matches!(self.state, DeviceState::Active(_))
+    }
+
+    pub(super) fn sessions(&self) -> impl Iterator<Item = &SessionInfo> {
+        self.session_map.values()
+    }
+}
+
+/// All the devices Windows knows about, kept up to date by feeding it the
+/// events its notification callbacks produce.
+pub(super) struct DeviceMap {
+    enumerator: IMMDeviceEnumerator,
+    map: HashMap<String, DeviceInfo>,
+    defaults: [[Option<String>; 2]; 3],
+    on_event: EventCallback,
+    notification_client: IMMNotificationClient,
+}
+
+impl DeviceMap {
+    pub(super) fn new(enumerator: &IMMDeviceEnumerator, on_event: EventCallback) -> Result<Self> {
+        let notification_client = IMMNotificationClient::from(MMNotificationClient {
+            on_event: on_event.clone(),
+        });
+        unsafe { enumerator.RegisterEndpointNotificationCallback(&notification_client) }?;
+        let mut device_map = Self {
+            enumerator: enumerator.clone(),
+            map: HashMap::new(),
+            defaults: [[None, None], [None, None], [None, None]],
+            on_event,
+            notification_client,
+        };
+        let all_states = DEVICE_STATE(
+            DEVICE_STATE_ACTIVE.0
+                | DEVICE_STATE_DISABLED.0
+                | DEVICE_STATE_NOTPRESENT.0
+                | DEVICE_STATE_UNPLUGGED.0,
+        );
+        let devices = unsafe { enumerator.EnumAudioEndpoints(eAll, all_states) }?;
+        for i in 0..unsafe { devices.GetCount() }? {
+            let device = unsafe { devices.Item(i) }?;
+            match DeviceInfo::new(device, device_map.on_event.clone()) {
+                Ok(device_info) => {
+                    device_map.map.insert(device_info.id.clone(), device_info);
+                }
+                Err(error) => debug!("Skipping device: {error}"),
+            }
+        }
+        for flow in [Flow::Render, Flow::Capture] {
+            for role in [Role::Console, Role::Multimedia, Role::Communications] {
+                // There might not be any device for this at all
+                let Ok(device) =
+                    (unsafe { enumerator.GetDefaultAudioEndpoint(flow.into(), role.into()) })
+                else {
+                    continue;
+                };
+                let device_id = unsafe { device.GetId()?.to_string() }?;
+                device_map.defaults[role as usize][flow as usize] = Some(device_id);
+            }
+        }
+        Ok(device_map)
+    }
+
+    pub(super) fn devices(&self) -> impl Iterator<Item = &DeviceInfo> {
+        self.map.values()
+    }
+
+    pub(super) fn get_default_device(&self, flow: Flow, role: Role) -> Option<&DeviceInfo> {
+        self.defaults[role as usize][flow as usize]
+            .as_ref()
+            .and_then(|id| self.map.get(id))
+    }
+
+    pub(super) fn handle_event(&mut self, event: AudioEvent) -> Result<Option<Change>> {
+        match event {
+            AudioEvent::Device(device_id, device_event) => {
+                self.handle_device_event(&device_id, device_event)
+            }
+            AudioEvent::Session(device_id, session_instance_id, session_event) => {
+                Ok(self.handle_session_event(&device_id, &session_instance_id, session_event))
+            }
+        }
+    }
+
+    fn handle_device_event(
+        &mut self,
+        device_id: &str,
+        device_event: DeviceEvent,
+    ) -> Result<Option<Change>> {
+        Ok(Some(match device_event {
+            DeviceEvent::DefaultDeviceChanged(flow, role) => {
+                self.defaults[role as usize][flow as usize] = Some(device_id.to_string());
+                let Some(device_info) = self.map.get(device_id) else {
+                    debug!("Device not found: {device_id}");
+                    return Ok(None);
+                };
+                Change::DefaultDevice {
+                    flow,
+                    role,
+                    device: device_info.name.clone(),
+                }
+            }
+            DeviceEvent::DeviceAdded => {
+                let device_id = wide_string(device_id);
+                let device = unsafe { self.enumerator.GetDevice(PCWSTR(device_id.as_ptr())) }?;
+                let device_info = DeviceInfo::new(device, self.on_event.clone())?;
+                let name = device_info.name.clone();
+                self.map.insert(device_info.id.clone(), device_info);
+                Change::DeviceAdded(name)
+            }
+            DeviceEvent::DeviceRemoved => {
+                let Some(removed) = self.map.remove(device_id) else {
+                    debug!("Device not found: {device_id}");
+                    return Ok(None);
+                };
+                Change::DeviceRemoved(removed.name)
+            }
+            DeviceEvent::DeviceStateChanged(new_state) => {
+                let Some(device_info) = self.map.get_mut(device_id) else {
+                    debug!("Device not found: {device_id}");
+                    return Ok(None);
+                };
+                device_info.set_state(new_state)?;
+                Change::DeviceState {
+                    device: device_info.name.clone(),
+                    state: device_info.state.to_string(),
+                }
+            }
+            DeviceEvent::SessionCreated(session_instance_id) => {
+                let Some(device_info) = self.map.get_mut(device_id) else {
+                    debug!("Device not found: {device_id}");
+                    return Ok(None);
+                };
+                let DeviceState::Active(session_manager) = &device_info.state else {
+                    debug!("Device not active: {}", device_info.name);
+                    return Ok(None);
+                };
+                let mut found = None;
+                for item in all_sessions(session_manager)? {
+                    let (instance_id, controls) = item?;
+                    if instance_id == session_instance_id {
+                        found = Some(controls);
+                        break;
+                    }
+                }
+                let Some((control, control2)) = found else {
+                    debug!("Session not found: {session_instance_id}");
+                    return Ok(None);
+                };
+                let session = SessionInfo::new(
+                    &device_info.id,
+                    session_instance_id.clone(),
+                    control,
+                    &control2,
+                    self.on_event.clone(),
+                )?;
+                let change = Change::SessionCreated {
+                    device: device_info.name.clone(),
+                    session: session.display_name().to_string(),
+                };
+                device_info.session_map.insert(session_instance_id, session);
+                change
+            }
+        }))
+    }
+
+    fn handle_session_event(
+        &mut self,
+        device_id: &str,
+        session_instance_id: &str,
+        session_event: SessionEvent,
+    ) -> Option<Change> {
+        let Some(device_info) = self.map.get_mut(device_id) else {
+            debug!("Device not found: {device_id}");
+            return None;
+        };
+        let Some(session_info) = device_info.session_map.get_mut(session_instance_id) else {
+            debug!(
+                "Session not found on {}: {session_instance_id}",
+                device_info.name
+            );
+            return None;
+        };
+        let device = device_info.name.clone();
+        let session = session_info.display_name().to_string();
+        match session_event {
+            SessionEvent::SimpleVolumeChanged { volume, mute } => {
+                debug!("Volume changed: Device={device}, Session={session}, Volume={volume}");
+                if session_info.mute == mute {
+                    return None;
+                }
+                session_info.mute = mute;
+                Some(Change::Mute {
+                    device,
+                    session,
+                    mute,
+                })
+            }
+            SessionEvent::DisplayNameChanged(new_display_name) => {
+                session_info.set_display_name(new_display_name);
+                debug!(
+                    "New display name: Device={device}, Session={session}, Name={}",
+                    session_info.display_name()
+                );
+                None
+            }
+            SessionEvent::GroupingParamChanged(new_grouping_param) => {
+                debug!(
+                    "New grouping param: Device={device}, Session={session}, Param={new_grouping_param}"
+                );
+                None
+            }
+            SessionEvent::IconPathChanged(new_icon_path) => {
+                debug!("New icon path: Device={device}, Session={session}, Path={new_icon_path}");
+                None
+            }
+            SessionEvent::StateChanged(new_state) => {
+                debug!("New state: Device={device}, Session={session}, State={new_state}");
+                None
+            }
+            SessionEvent::SessionDisconnected(reason) => {
+                device_info.session_map.remove(session_instance_id);
+                debug!("Session disconnected: Device={device}, Session={session}, Reason={reason}");
+                Some(Change::SessionRemoved { device, session })
+            }
+        }
+    }
+}
+
+impl Drop for DeviceMap {
+    fn drop(&mut self) {
+        // Nothing sensible to do about a failure while dropping
+        let _ = unsafe {
+            self.enumerator
+                .UnregisterEndpointNotificationCallback(&self.notification_client)
+        };
+    }
+}
+
+#[allow(clippy::type_complexity)]
+fn all_sessions(
+    session_manager_2: &IAudioSessionManager2,
+) -> Result<impl Iterator<Item = Result<(String, (IAudioSessionControl, IAudioSessionControl2))>>> {
+    let session_collection = unsafe { session_manager_2.GetSessionEnumerator() }?;
+    Ok(
+        (0..unsafe { session_collection.GetCount() }?).map(move |i| {
+            let session_control = unsafe { session_collection.GetSession(i) }?;
+            let session_control_2 = session_control.cast::<IAudioSessionControl2>()?;
+            let session_instance_id = unsafe {
+                session_control_2
+                    .GetSessionInstanceIdentifier()?
+                    .to_string()
+            }?;
+            Ok((session_instance_id, (session_control, session_control_2)))
+        }),
+    )
+}
+
+impl TryFrom<WindowsEDataFlow> for Flow {
+    type Error = windows::core::Error;
+    fn try_from(value: WindowsEDataFlow) -> windows::core::Result<Self> {
+        match value {
+            eRender => Ok(Flow::Render),
+            eCapture => Ok(Flow::Capture),
+            _ => Err(windows::core::Error::new(
+                HRESULT(BAD_VALUE),
+                "Bad value for flow",
+            )),
+        }
+    }
+}
+
+impl TryFrom<WindowsERole> for Role {
+    type Error = windows::core::Error;
+    fn try_from(value: WindowsERole) -> windows::core::Result<Self> {
+        match value {
+            eConsole => Ok(Role::Console),
+            eMultimedia => Ok(Role::Multimedia),
+            eCommunications => Ok(Role::Communications),
+            _ => Err(windows::core::Error::new(
+                HRESULT(BAD_VALUE),
+                "Bad value for role",
+            )),
+        }
+    }
+}
+
+#[implement(IAudioSessionNotification)]
+struct AudioSessionNotification {
+    device_id: String,
+    on_event: EventCallback,
+}
+
+impl IAudioSessionNotification_Impl for AudioSessionNotification {
+    fn OnSessionCreated(
+        &self,
+        session: Option<&IAudioSessionControl>,
+    ) -> windows::core::Result<()> {
+        let Some(session) = session else {
+            return Ok(());
+        };
+        let session_control_2 = session.cast::<IAudioSessionControl2>()?;
+        let session_instance_id = unsafe {
+            session_control_2
+                .GetSessionInstanceIdentifier()?
+                .to_string()
+        }?;
+        (self.on_event)(AudioEvent::Device(
+            self.device_id.clone(),
+            DeviceEvent::SessionCreated(session_instance_id),
+        ));
+        Ok(())
+    }
+}
+
+#[implement(IMMNotificationClient)]
+struct MMNotificationClient {
+    on_event: EventCallback,
+}
+
+impl IMMNotificationClient_Impl for MMNotificationClient {
+    fn OnDefaultDeviceChanged(
+        &self,
+        flow: WindowsEDataFlow,
+        role: WindowsERole,
+        default_device_id: &PCWSTR,
+    ) -> windows::core::Result<()> {
+        let flows = if flow == eAll {
+            vec![Flow::Render, Flow::Capture]
+        } else {
+            vec![Flow::try_from(flow)?]
+        };
+        let role = Role::try_from(role)?;
+        let default_device_id = unsafe { default_device_id.to_string()? };
+        for flow in flows {
+            (self.on_event)(AudioEvent::Device(
+                default_device_id.clone(),
+                DeviceEvent::DefaultDeviceChanged(flow, role),
+            ));
+        }
+        Ok(())
+    }
+
+    fn OnDeviceAdded(&self, device_id: &PCWSTR) -> windows::core::Result<()> {
+        let device_id = unsafe { device_id.to_string()? };
+        (self.on_event)(AudioEvent::Device(device_id, DeviceEvent::DeviceAdded));
+        Ok(())
+    }
+
+    fn OnDeviceRemoved(&self, device_id: &PCWSTR) -> windows::core::Result<()> {
+        let device_id = unsafe { device_id.to_string()? };
+        (self.on_event)(AudioEvent::Device(device_id, DeviceEvent::DeviceRemoved));
+        Ok(())
+    }
+
+    fn OnDeviceStateChanged(
+        &self,
+        device_id: &PCWSTR,
+        new_state: DEVICE_STATE,
+    ) -> windows::core::Result<()> {
+        let device_id = unsafe { device_id.to_string()? };
+        (self.on_event)(AudioEvent::Device(
+            device_id,
+            DeviceEvent::DeviceStateChanged(new_state),
+        ));
+        Ok(())
+    }
+
+    fn OnPropertyValueChanged(
+        &self,
+        _device_id: &PCWSTR,
+        _key: &PROPERTYKEY,
+    ) -> windows::core::Result<()> {
+        Ok(())
+    }
+}
+
+#[implement(IAudioSessionEvents)]
+struct AudioSessionEvents {
+    device_id: String,
+    session_instance_id: String,
+    on_event: EventCallback,
+}
+
+impl AudioSessionEvents {
+    fn send(&self, event: SessionEvent) {
+        (self.on_event)(AudioEvent::Session(
+            self.device_id.clone(),
+            self.session_instance_id.clone(),
+            event,
+        ));
+    }
+}
+
+impl IAudioSessionEvents_Impl for AudioSessionEvents {
+    fn OnDisplayNameChanged(
+        &self,
+        new_display_name: &PCWSTR,
+        _event_context: *const GUID,
+    ) -> windows::core::Result<()> {
+        let new_display_name = unsafe { new_display_name.to_string()? };
+        self.send(SessionEvent::DisplayNameChanged(new_display_name));
+        Ok(())
+    }
+
+    fn OnIconPathChanged(
+        &self,
+        new_icon_path: &PCWSTR,
+        _event_context: *const GUID,
+    ) -> windows::core::Result<()> {
+        let new_icon_path = unsafe { new_icon_path.to_string()? };
+        self.send(SessionEvent::IconPathChanged(new_icon_path));
+        Ok(())
+    }
+
+    fn OnSimpleVolumeChanged(
+        &self,
+        new_volume: f32,
+        new_mute: BOOL,
+        _event_context: *const GUID,
+    ) -> windows::core::Result<()> {
+        self.send(SessionEvent::SimpleVolumeChanged {
+            volume: new_volume,
+            mute: new_mute == TRUE,
+        });
+        Ok(())
+    }
+
+    // This is too low level for right now, we just care about the SimpleVolume
+    // changes. As per
+    // https://learn.microsoft.com/en-us/windows/win32/api/audioclient/nn-audioclient-ichannelaudiovolume
+    // channel volume is multiplied with simple volume and otherwise do not
+    // influence eachother, so if we just ignore per channel volume, we're not
+    // messing anything up either.
+    fn OnChannelVolumeChanged(
+        &self,
+        _channel_count: u32,
+        _new_channel_volume_array: *const f32,
+        _changed_channel: u32,
+        _event_context: *const GUID,
+    ) -> windows::core::Result<()> {
+        Ok(())
+    }
+
+    fn OnGroupingParamChanged(
+        &self,
+        new_grouping_param: *const GUID,
+        _event_context: *const GUID,
+    ) -> windows::core::Result<()> {
+        let Some(new_grouping_param) = (unsafe { new_grouping_param.as_ref() }) else {
+            return Ok(());
+        };
+        self.send(SessionEvent::GroupingParamChanged(
+            new_grouping_param.to_u128(),
+        ));
+        Ok(())
+    }
+
+    fn OnStateChanged(&self, new_state: AudioSessionState) -> windows::core::Result<()> {
+        let new_state = SessionState::try_from(new_state)?;
+        if new_state == SessionState::Expired {
+            self.send(SessionEvent::SessionDisconnected(
+                DisconnectReason::SessionExpired,
+            ));
+        }
+        self.send(SessionEvent::StateChanged(new_state));
+        Ok(())
+    }
+
+    fn OnSessionDisconnected(
+        &self,
+        disconnect_reason: AudioSessionDisconnectReason,
+    ) -> windows::core::Result<()> {
+        let disconnect_reason = DisconnectReason::try_from(disconnect_reason)?;
+        self.send(SessionEvent::SessionDisconnected(disconnect_reason));
+        Ok(())
+    }
+}
@@ -0,0 +1,156 @@
+use std::{ptr, sync::OnceLock, thread};
+
+use windows::{
+    core::{w, HRESULT, PWSTR},
+    Win32::{
+        Foundation::{GetLastError, HWND},
+        System::{
+            Diagnostics::Debug::{
+                FormatMessageW, FORMAT_MESSAGE_FROM_SYSTEM, FORMAT_MESSAGE_IGNORE_INSERTS,
+            },
+            SystemServices::LANG_NEUTRAL,
+        },
+        UI::{
+            Accessibility::{SetWinEventHook, HWINEVENTHOOK},
+            WindowsAndMessaging::{
+                CreateWindowExW, DestroyWindow, GetForegroundWindow, GetMessageW,
+                GetWindowThreadProcessId, EVENT_SYSTEM_FOREGROUND, HWND_MESSAGE, MSG,
+                WINDOW_EX_STYLE, WINDOW_STYLE, WINEVENT_OUTOFCONTEXT,
+            },
+        },
+    },
+};
+
+use crate::error::Result;
+
+type ForegroundCallback = Box<dyn Fn(u32) + Send + Sync>;
+
+// The hook callback doesn't get any context, so this is the only way to get
+// `on_change` to it
+static FOREGROUND_CALLBACK: OnceLock<ForegroundCallback> = OnceLock::new();
+
+/// Calls `on_change` with the process id of the foreground window right away,
+/// and again whenever another window comes to the foreground. Can only be
+/// used once.
+pub fn watch_foreground<F>(on_change: F) -> Result<()>
+where
+    F: Fn(u32) + Send + Sync + 'static,
+{
+    if FOREGROUND_CALLBACK.set(Box::new(on_change)).is_err() {
+        return Err(windows::core::Error::new(
+            HRESULT::default(),
+            "Already watching the foreground window",
+        )
+        .into());
+    }
+    // The message loop that is required to receive the events needs to be in
+    // the same thread as the one that calls SetWinEventHook. So make a thread
+    // now that handles both.
+    let (tx, rx) = oneshot::channel();
+    thread::spawn(move || {
+        // We don't want WINEVENT_SKIPOWNPROCESS, because we want to know when
+        // an audio producing app is no longer the foreground window, even if
+        // it is us taking its place. WINEVENT_OUTOFCONTEXT because we aren't
+        // mapped into the address space of any of the other processes.
+        let event_hook = unsafe {
+            SetWinEventHook(
+                EVENT_SYSTEM_FOREGROUND,
+                EVENT_SYSTEM_FOREGROUND,
+                None,
+                Some(win_event_hook_callback),
+                0,
+                0,
+                WINEVENT_OUTOFCONTEXT,
+            )
+        };
+        // If the other side is gone there's nobody to tell about failures
+        let _ = tx.send(event_hook);
+        if event_hook.is_invalid() {
+            return;
+        }
+        let foreground = unsafe { GetForegroundWindow() };
+        let mut window_pid: u32 = 0;
+        let _ =
+            unsafe { GetWindowThreadProcessId(foreground, Some(ptr::addr_of_mut!(window_pid))) };
+        (FOREGROUND_CALLBACK.get().unwrap())(window_pid);
+        // This thread needs to own a window to receive messages
+        let window = MessageLoopWindow::new().unwrap();
+        let mut msg = MSG::default();
+        loop {
+            unsafe {
+                let _ = GetMessageW(ptr::addr_of_mut!(msg), window.0, 0, 0);
+            }
+        }
+    });
+    let event_hook: HWINEVENTHOOK = rx.recv().unwrap();
+    if event_hook.is_invalid() {
+        return Err(windows::core::Error::new(HRESULT::default(), "SetWinEventHook failed").into());
+    }
+    Ok(())
+}
+
+unsafe extern "system" fn win_event_hook_callback(
+    _h_win_event_hook: HWINEVENTHOOK,
+    event: u32,
+    hwnd: HWND,
+    _id_object: i32,
+    _id_child: i32,
+    _id_event_thread: u32,
+    _dwms_event_time: u32,
+) {
+    if event != EVENT_SYSTEM_FOREGROUND {
+        return;
+    }
+    let mut pid: u32 = 0;
+    let _ = unsafe { GetWindowThreadProcessId(hwnd, Some(ptr::addr_of_mut!(pid))) };
+    if let Some(on_change) = FOREGROUND_CALLBACK.get() {
+        on_change(pid);
+    }
+}
+
+struct MessageLoopWindow(HWND);
+
+impl MessageLoopWindow {
+    fn new() -> windows::core::Result<Self> {
+        let window = unsafe {
+            CreateWindowExW(
+                WINDOW_EX_STYLE(0), // no extended style
+                w!("STATIC"),
+                w!("MessageLoopWindow"),
+                WINDOW_STYLE(0), // no style
+                0,
+                0,
+                0,
+                0,
+                HWND_MESSAGE,
+                None,
+                None,
+                None,
+            )
+        };
+        if window == HWND::default() {
+            return Err(get_last_error());
+        }
+        Ok(Self(window))
+    }
+}
+
+impl Drop for MessageLoopWindow {
+    fn drop(&mut self) {
+        unsafe {
+            DestroyWindow(self.0).unwrap();
+        }
+    }
+}
+
+fn get_last_error() -> windows::core::Error {
+    let error = unsafe { GetLastError() };
+    let flags = FORMAT_MESSAGE_FROM_SYSTEM | FORMAT_MESSAGE_IGNORE_INSERTS;
+    let mut buf = [0u16; 256];
+    let message: PWSTR = PWSTR(buf.as_mut_ptr());
+    unsafe {
+        FormatMessageW(flags, None, error.0, LANG_NEUTRAL, message, 256, None);
+    }
+    let message = unsafe { message.to_string() }.unwrap();
+    windows::core::Error::new(HRESULT::from(error), message)
+}
@@ -0,0 +1,58 @@
+// The method names have to match the interface, and the macro's expansion
+// isn't pedantic-clean
+#![allow(non_snake_case, clippy::transmute_ptr_to_ptr)]
+
+use std::ffi::c_void;
+
+use windows::{
+    core::{interface, IUnknown, IUnknown_Vtbl, GUID, HRESULT, PCWSTR},
+    Win32::{Foundation::BOOL, Media::Audio::ERole},
+};
+
+pub(super) const CPOLICY_CONFIG_CLIENT: GUID =
+    GUID::from_u128(0x870a_f99c_171d_4f9e_af0d_e63d_f40c_2bc9);
+
+/// The undocumented interface that the Sound control panel uses to change the
+/// default devices. Only `SetDefaultEndpoint` is used, the rest are only
+/// there to get it in the right spot in the vtable.
+#[interface("f8679f50-850a-41cf-9c72-430f290290c8")]
+pub(super) unsafe trait IPolicyConfig: IUnknown {
+    fn GetMixFormat(&self, device_id: PCWSTR, format: *mut *mut c_void) -> HRESULT;
+    fn GetDeviceFormat(
+        &self,
+        device_id: PCWSTR,
+        default: BOOL,
+        format: *mut *mut c_void,
+    ) -> HRESULT;
+    fn ResetDeviceFormat(&self, device_id: PCWSTR) -> HRESULT;
+    fn SetDeviceFormat(
+        &self,
+        device_id: PCWSTR,
+        endpoint_format: *mut c_void,
+        mix_format: *mut c_void,
+    ) -> HRESULT;
+    fn GetProcessingPeriod(
+        &self,
+        device_id: PCWSTR,
+        default: BOOL,
+        default_period: *mut i64,
+        minimum_period: *mut i64,
+    ) -> HRESULT;
+    fn SetProcessingPeriod(&self, device_id: PCWSTR, period: *mut i64) -> HRESULT;
+    fn GetShareMode(&self, device_id: PCWSTR, mode: *mut c_void) -> HRESULT;
+    fn SetShareMode(&self, device_id: PCWSTR, mode: *mut c_void) -> HRESULT;
+    fn GetPropertyValue(
+        &self,
+        device_id: PCWSTR,
+        key: *const c_void,
+        value: *mut c_void,
+    ) -> HRESULT;
+    fn SetPropertyValue(
+        &self,
+        device_id: PCWSTR,
+        key: *const c_void,
+        value: *mut c_void,
+    ) -> HRESULT;
+    pub fn SetDefaultEndpoint(&self, device_id: PCWSTR, role: ERole) -> HRESULT;
+    fn SetEndpointVisibility(&self, device_id: PCWSTR, visible: BOOL) -> HRESULT;
+}